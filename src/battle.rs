@@ -0,0 +1,575 @@
+use crate::items::ItemEffect;
+use frenderer::sprites::SheetRegion;
+use std::collections::VecDeque;
+
+/*
+A battle is a list of combatants, the party's first and then the enemies', who take
+turns in order of speed.  Every round, everyone still standing gets one turn, fastest
+first (the party goes first in a tie).  Whoever's turn it is picks an Action, either
+through the battle menus or through enemy_action(), and act() carries it out and
+moves on to the next turn.
+
+The battle is over once one side has nobody standing or the party runs away.
+
+A combatant's stats are its base stats plus whatever its equipment adds plus any buffs
+picked up during the battle.  Everything that needs a stat reads it through
+Combatant::stats(), so damage, turn order and healing caps always agree.
+*/
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub max_hp: u32,
+    pub max_mp: u32,
+    pub attack: u32,
+    pub defense: u32,
+    pub speed: u32,
+}
+
+impl Stats {
+    /// Adds up two sets of stats, stat by stat
+    pub fn plus(self, other: Stats) -> Stats {
+        Stats {
+            max_hp: self.max_hp + other.max_hp,
+            max_mp: self.max_mp + other.max_mp,
+            attack: self.attack + other.attack,
+            defense: self.defense + other.defense,
+            speed: self.speed + other.speed,
+        }
+    }
+}
+
+/// The stats someone actually has: their base stats, plus what their equipment adds,
+/// plus temporary buffs
+pub fn derived_stats(base: Stats, gear: Stats, buffs: Stats) -> Stats {
+    base.plus(gear).plus(buffs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkillKind {
+    // hits one enemy for this much plus half the user's attack, mostly ignoring defense
+    Damage(u32),
+    // restores this much HP to one ally
+    Heal(u32),
+    // raises one ally's attack by this much for the rest of the battle
+    Boost(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Skill {
+    pub name: &'static str,
+    pub mp: u32,
+    pub kind: SkillKind,
+}
+
+pub const SKILLS: &[Skill] = &[
+    Skill {
+        name: "Fire",
+        mp: 3,
+        kind: SkillKind::Damage(6),
+    },
+    Skill {
+        name: "Heal",
+        mp: 4,
+        kind: SkillKind::Heal(15),
+    },
+    Skill {
+        name: "Rally",
+        mp: 3,
+        kind: SkillKind::Boost(3),
+    },
+];
+
+/// Looks up a skill in SKILLS by name, ignoring case
+pub fn skill_named(name: &str) -> Option<usize> {
+    SKILLS
+        .iter()
+        .position(|s| s.name.eq_ignore_ascii_case(name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Party,
+    Enemy,
+}
+
+#[derive(Debug, Clone)]
+pub struct Combatant {
+    pub name: String,
+    pub side: Side,
+    pub base: Stats,
+    // bonuses from equipment
+    pub gear: Stats,
+    // bonuses picked up during the battle, which wear off once it's over
+    pub buffs: Stats,
+    pub hp: u32,
+    pub mp: u32,
+    // indices into SKILLS
+    pub skills: Vec<usize>,
+    pub defending: bool,
+    pub sprite: SheetRegion,
+}
+
+impl Combatant {
+    /// A combatant with no equipment at full HP and MP
+    pub fn new(
+        name: &str,
+        side: Side,
+        stats: Stats,
+        skills: Vec<usize>,
+        sprite: SheetRegion,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            side,
+            base: stats,
+            gear: Stats::default(),
+            buffs: Stats::default(),
+            hp: stats.max_hp,
+            mp: stats.max_mp,
+            skills,
+            defending: false,
+            sprite,
+        }
+    }
+    pub fn alive(&self) -> bool {
+        self.hp > 0
+    }
+    pub fn stats(&self) -> Stats {
+        derived_stats(self.base, self.gear, self.buffs)
+    }
+    fn heal(&mut self, amount: u32) -> u32 {
+        let healed = amount.min(self.stats().max_hp.saturating_sub(self.hp));
+        self.hp += healed;
+        healed
+    }
+    /// Applies an item's effects, returning a description like "+20 HP"
+    pub fn apply_item(&mut self, effects: &[ItemEffect]) -> String {
+        let results: Vec<String> = effects
+            .iter()
+            .map(|effect| match effect {
+                ItemEffect::Heal(amount) => format!("+{} HP", self.heal(*amount)),
+                ItemEffect::RestoreMp(amount) => {
+                    let restored = (*amount).min(self.stats().max_mp.saturating_sub(self.mp));
+                    self.mp += restored;
+                    format!("+{restored} MP")
+                }
+            })
+            .collect();
+        if results.is_empty() {
+            "nothing happens".to_string()
+        } else {
+            results.join(" ")
+        }
+    }
+    fn hurt(&mut self, amount: u32) -> u32 {
+        let amount = if self.defending {
+            (amount / 2).max(1)
+        } else {
+            amount
+        };
+        let hurt = amount.min(self.hp);
+        self.hp -= hurt;
+        hurt
+    }
+}
+
+/// What a combatant does with its turn.  Targets are indices into Battle::combatants().
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Attack(usize),
+    // index into SKILLS, then the target
+    Skill(usize, usize),
+    // items are looked up by the caller, who also takes them out of the inventory
+    Item {
+        name: String,
+        effects: Vec<ItemEffect>,
+        target: usize,
+    },
+    Defend,
+    Flee,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Victory,
+    Defeat,
+    Fled,
+}
+
+pub fn attack_damage(attack: u32, defense: u32) -> u32 {
+    attack.saturating_sub(defense / 2).max(1)
+}
+
+pub fn skill_damage(power: u32, attack: u32, defense: u32) -> u32 {
+    (power + attack / 2).saturating_sub(defense / 4).max(1)
+}
+
+#[derive(Debug, Clone)]
+pub struct Battle {
+    combatants: Vec<Combatant>,
+    // who's left to act this round, front first
+    queue: VecDeque<usize>,
+    round: usize,
+    outcome: Option<Outcome>,
+}
+
+impl Battle {
+    pub fn new(party: Vec<Combatant>, enemies: Vec<Combatant>) -> Self {
+        let mut battle = Self {
+            combatants: party.into_iter().chain(enemies).collect(),
+            queue: VecDeque::new(),
+            round: 0,
+            outcome: None,
+        };
+        battle.check_outcome();
+        battle.next_turn();
+        battle
+    }
+    pub fn combatants(&self) -> &[Combatant] {
+        &self.combatants
+    }
+    /// Indices of everyone on one side, standing or not
+    pub fn side(&self, side: Side) -> Vec<usize> {
+        (0..self.combatants.len())
+            .filter(|&c| self.combatants[c].side == side)
+            .collect()
+    }
+    /// Indices of everyone on one side who's still standing
+    pub fn standing(&self, side: Side) -> Vec<usize> {
+        self.side(side)
+            .into_iter()
+            .filter(|&c| self.combatants[c].alive())
+            .collect()
+    }
+    /// Whose turn it is, or None once the battle is over
+    pub fn current(&self) -> Option<usize> {
+        if self.outcome.is_some() {
+            None
+        } else {
+            self.queue.front().copied()
+        }
+    }
+    pub fn round(&self) -> usize {
+        self.round
+    }
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+    /// The party as it stands now, to carry the battle's results back to the map
+    pub fn party(&self) -> Vec<Combatant> {
+        self.side(Side::Party)
+            .into_iter()
+            .map(|c| Combatant {
+                defending: false,
+                buffs: Stats::default(),
+                ..self.combatants[c].clone()
+            })
+            .collect()
+    }
+    // Drops anyone who can't act from the front of the queue, starting a new round if needed
+    fn next_turn(&mut self) {
+        while self.outcome.is_none() {
+            match self.queue.front() {
+                Some(&c) if self.combatants[c].alive() => {
+                    // defending lasts until the defender's next turn
+                    self.combatants[c].defending = false;
+                    return;
+                }
+                Some(_) => {
+                    self.queue.pop_front();
+                }
+                None => {
+                    self.round += 1;
+                    let mut order: Vec<usize> = (0..self.combatants.len())
+                        .filter(|&c| self.combatants[c].alive())
+                        .collect();
+                    // stable, so ties keep the party ahead and everyone in list order
+                    order.sort_by_key(|&c| std::cmp::Reverse(self.combatants[c].stats().speed));
+                    self.queue = order.into();
+                }
+            }
+        }
+    }
+    fn check_outcome(&mut self) {
+        if self.standing(Side::Party).is_empty() {
+            self.outcome = Some(Outcome::Defeat);
+        } else if self.standing(Side::Enemy).is_empty() {
+            self.outcome = Some(Outcome::Victory);
+        }
+    }
+    /// Whether the current combatant could use this skill right now
+    pub fn can_use(&self, skill: usize) -> bool {
+        self.current()
+            .is_some_and(|c| self.combatants[c].mp >= SKILLS[skill].mp)
+    }
+    /// Carries out the current combatant's action, then moves on to the next turn.
+    /// Returns a message describing what happened.
+    pub fn act(&mut self, action: Action) -> String {
+        let Some(actor) = self.current() else {
+            return String::new();
+        };
+        let name = self.combatants[actor].name.clone();
+        let message = match action {
+            Action::Attack(target) => {
+                let dmg = attack_damage(
+                    self.combatants[actor].stats().attack,
+                    self.combatants[target].stats().defense,
+                );
+                let dmg = self.combatants[target].hurt(dmg);
+                format!("{name} hits {} for {dmg}!", self.combatants[target].name)
+            }
+            Action::Skill(skill, target) => {
+                let skill = SKILLS[skill];
+                if self.combatants[actor].mp < skill.mp {
+                    format!("{name} doesn't have the MP for {}.", skill.name)
+                } else {
+                    self.combatants[actor].mp -= skill.mp;
+                    let target_name = self.combatants[target].name.clone();
+                    match skill.kind {
+                        SkillKind::Damage(power) => {
+                            let dmg = skill_damage(
+                                power,
+                                self.combatants[actor].stats().attack,
+                                self.combatants[target].stats().defense,
+                            );
+                            let dmg = self.combatants[target].hurt(dmg);
+                            format!("{name} casts {} on {target_name} for {dmg}!", skill.name)
+                        }
+                        SkillKind::Heal(amount) => {
+                            let healed = self.combatants[target].heal(amount);
+                            format!("{name} casts {}; {target_name} +{healed} HP", skill.name)
+                        }
+                        SkillKind::Boost(amount) => {
+                            self.combatants[target].buffs.attack += amount;
+                            format!("{name} casts {}; {target_name} +{amount} ATK", skill.name)
+                        }
+                    }
+                }
+            }
+            Action::Item {
+                name: item,
+                effects,
+                target,
+            } => {
+                let result = self.combatants[target].apply_item(&effects);
+                format!(
+                    "{name} uses {item}; {} {result}",
+                    self.combatants[target].name
+                )
+            }
+            Action::Defend => {
+                self.combatants[actor].defending = true;
+                format!("{name} defends.")
+            }
+            Action::Flee => {
+                // you get away from anything that isn't faster than you on average
+                let enemies = self.standing(Side::Enemy);
+                let enemy_speed = enemies
+                    .iter()
+                    .map(|&c| self.combatants[c].stats().speed)
+                    .sum::<u32>()
+                    / enemies.len().max(1) as u32;
+                if self.combatants[actor].stats().speed >= enemy_speed {
+                    self.outcome = Some(Outcome::Fled);
+                    "Got away safely!".to_string()
+                } else {
+                    "Couldn't get away!".to_string()
+                }
+            }
+        };
+        self.queue.pop_front();
+        if self.outcome.is_none() {
+            self.check_outcome();
+        }
+        self.next_turn();
+        message
+    }
+    /// Picks an action for the current combatant: heal when badly hurt, cast when it can,
+    /// and otherwise hit whoever on the other side is closest to going down.
+    pub fn enemy_action(&self) -> Action {
+        let Some(actor) = self.current() else {
+            return Action::Defend;
+        };
+        let me = &self.combatants[actor];
+        let foes = self.standing(match me.side {
+            Side::Party => Side::Enemy,
+            Side::Enemy => Side::Party,
+        });
+        let Some(&weakest) = foes.iter().min_by_key(|&&c| self.combatants[c].hp) else {
+            return Action::Defend;
+        };
+        let usable = |kind: fn(SkillKind) -> bool| {
+            me.skills
+                .iter()
+                .copied()
+                .find(|&s| kind(SKILLS[s].kind) && me.mp >= SKILLS[s].mp)
+        };
+        if me.hp * 3 <= me.stats().max_hp {
+            if let Some(heal) = usable(|k| matches!(k, SkillKind::Heal(_))) {
+                return Action::Skill(heal, actor);
+            }
+        }
+        // save some MP by only casting every other round
+        if self.round.is_multiple_of(2) {
+            if let Some(hit) = usable(|k| matches!(k, SkillKind::Damage(_))) {
+                return Action::Skill(hit, weakest);
+            }
+        }
+        Action::Attack(weakest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn fighter(
+        name: &str,
+        side: Side,
+        hp: u32,
+        attack: u32,
+        defense: u32,
+        speed: u32,
+    ) -> Combatant {
+        Combatant::new(
+            name,
+            side,
+            Stats {
+                max_hp: hp,
+                max_mp: 10,
+                attack,
+                defense,
+                speed,
+            },
+            vec![],
+            SheetRegion::ZERO,
+        )
+    }
+    #[test]
+    fn test_turn_order() {
+        let mut battle = Battle::new(
+            vec![fighter("Hero", Side::Party, 30, 5, 2, 4)],
+            vec![
+                fighter("Slow", Side::Enemy, 10, 3, 0, 2),
+                fighter("Fast", Side::Enemy, 10, 3, 0, 6),
+                fighter("Tied", Side::Enemy, 10, 3, 0, 4),
+            ],
+        );
+        let mut order = vec![];
+        for _ in 0..4 {
+            order.push(battle.current().unwrap());
+            battle.act(Action::Defend);
+        }
+        assert_eq!(order, vec![2, 0, 3, 1]);
+        assert_eq!(battle.round(), 2);
+        assert_eq!(battle.current(), Some(2));
+    }
+    #[test]
+    fn test_damage() {
+        assert_eq!(attack_damage(10, 4), 8);
+        assert_eq!(attack_damage(2, 10), 1);
+        assert_eq!(skill_damage(6, 4, 8), 6);
+        let mut battle = Battle::new(
+            vec![fighter("Hero", Side::Party, 30, 10, 4, 5)],
+            vec![fighter("Slime", Side::Enemy, 20, 6, 2, 3)],
+        );
+        assert_eq!(battle.act(Action::Defend), "Hero defends.");
+        // hero's defending, so the slime's 4 damage is halved
+        assert_eq!(battle.enemy_action(), Action::Attack(0));
+        assert_eq!(battle.act(Action::Attack(0)), "Slime hits Hero for 2!");
+        assert_eq!(battle.act(Action::Attack(1)), "Hero hits Slime for 9!");
+        assert_eq!(battle.act(Action::Attack(0)), "Slime hits Hero for 4!");
+        assert_eq!(battle.combatants()[0].hp, 24);
+        assert_eq!(battle.combatants()[1].hp, 11);
+        assert_eq!(
+            battle.act(Action::Item {
+                name: "Potion".to_string(),
+                effects: vec![ItemEffect::Heal(20), ItemEffect::RestoreMp(5)],
+                target: 0,
+            }),
+            "Hero uses Potion; Hero +6 HP +0 MP"
+        );
+        assert_eq!(battle.combatants()[0].hp, 30);
+    }
+    #[test]
+    fn test_derived_stats() {
+        let mut hero = fighter("Hero", Side::Party, 30, 10, 4, 5);
+        hero.gear = Stats {
+            attack: 4,
+            defense: 4,
+            ..Stats::default()
+        };
+        assert_eq!(hero.stats().attack, 14);
+        let mut battle = Battle::new(vec![hero], vec![fighter("Slime", Side::Enemy, 20, 6, 2, 3)]);
+        assert_eq!(battle.act(Action::Attack(1)), "Hero hits Slime for 13!");
+        assert_eq!(battle.act(Action::Attack(0)), "Slime hits Hero for 2!");
+        let rally = skill_named("rally").unwrap();
+        assert_eq!(
+            battle.act(Action::Skill(rally, 0)),
+            "Hero casts Rally; Hero +3 ATK"
+        );
+        assert_eq!(battle.combatants()[0].stats().attack, 17);
+        // buffs are gone after the battle, but equipment stays on
+        assert_eq!(battle.party()[0].stats().attack, 14);
+    }
+    #[test]
+    fn test_outcomes() {
+        let mut battle = Battle::new(
+            vec![fighter("Hero", Side::Party, 30, 10, 4, 5)],
+            vec![fighter("Slime", Side::Enemy, 5, 6, 2, 3)],
+        );
+        battle.act(Action::Attack(1));
+        assert_eq!(battle.outcome(), Some(Outcome::Victory));
+        assert_eq!(battle.current(), None);
+
+        let mut battle = Battle::new(
+            vec![fighter("Hero", Side::Party, 3, 10, 0, 1)],
+            vec![fighter("Wolf", Side::Enemy, 50, 6, 2, 3)],
+        );
+        assert_eq!(battle.act(Action::Attack(0)), "Wolf hits Hero for 3!");
+        assert_eq!(battle.outcome(), Some(Outcome::Defeat));
+
+        let mut battle = Battle::new(
+            vec![fighter("Hero", Side::Party, 30, 10, 0, 1)],
+            vec![fighter("Wolf", Side::Enemy, 50, 6, 2, 3)],
+        );
+        battle.act(Action::Defend);
+        assert_eq!(battle.act(Action::Flee), "Couldn't get away!");
+        assert_eq!(battle.outcome(), None);
+        let mut battle = Battle::new(
+            vec![fighter("Hero", Side::Party, 30, 10, 0, 5)],
+            vec![fighter("Wolf", Side::Enemy, 50, 6, 2, 3)],
+        );
+        assert_eq!(battle.act(Action::Flee), "Got away safely!");
+        assert_eq!(battle.outcome(), Some(Outcome::Fled));
+        assert_eq!(battle.party()[0].hp, 30);
+    }
+    #[test]
+    fn test_enemy_ai() {
+        let fire = skill_named("fire").unwrap();
+        let heal = skill_named("heal").unwrap();
+        let mut mage = fighter("Mage", Side::Enemy, 30, 4, 0, 9);
+        mage.skills = vec![fire, heal];
+        let mut battle = Battle::new(
+            vec![
+                fighter("Hero", Side::Party, 30, 5, 0, 1),
+                fighter("Pal", Side::Party, 20, 5, 0, 1),
+            ],
+            vec![mage],
+        );
+        // odd round, so no casting yet; go for the pal, who has less HP
+        assert_eq!(battle.enemy_action(), Action::Attack(1));
+        battle.act(Action::Attack(1));
+        battle.act(Action::Defend);
+        battle.act(Action::Attack(2));
+        assert_eq!(battle.enemy_action(), Action::Skill(fire, 1));
+        battle.act(Action::Skill(fire, 1));
+        assert_eq!(battle.combatants()[2].mp, 7);
+        battle.act(Action::Attack(2));
+        battle.act(Action::Attack(2));
+        // the mage is down to 15 HP; hurt it some more so it wants to heal
+        battle.combatants[2].hp = 10;
+        assert_eq!(battle.enemy_action(), Action::Skill(heal, 2));
+        battle.combatants[2].mp = 0;
+        assert_eq!(battle.enemy_action(), Action::Attack(1));
+    }
+}
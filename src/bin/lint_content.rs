@@ -0,0 +1,632 @@
+//! Checks the hand-authored files in the content directory for mistakes
+//! that would otherwise only show up once the game runs into them, like
+//! doors to missing rooms, NPCs with dialog that isn't in `dialog.txt`, or
+//! dialog that starts a battle with an enemy group that isn't in `enemies.txt`
+//! or gives out an item (or a chest holds one) that isn't in `items.txt`.
+//! Markup in dialog pages that the game can't make sense of, like unknown
+//! variables, gets reported too.
+//! Levels are found through `world.txt`, and any `level*.txt` it doesn't
+//! list gets reported too.
+//!
+//! Usage: `cargo run --bin lint_content [CONTENT_DIR]`
+//!
+//! Prints every problem as `file:line: message` and exits with a nonzero
+//! status if there were any.
+use graphical::classes::Classes;
+use graphical::dialog::{Dialogs, Effect};
+use graphical::enemies::Enemies;
+use graphical::geom::Vec2;
+use graphical::items::Items;
+use graphical::level::Level;
+use graphical::markup::{self, Markup, Var};
+use graphical::world::World;
+use graphical::EntityType;
+use std::path::{Path, PathBuf};
+
+struct Problem {
+    file: PathBuf,
+    // 0 if the problem isn't about a particular line or column
+    line: usize,
+    col: usize,
+    message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if self.line != 0 {
+            write!(f, ":{}", self.line)?;
+        }
+        if self.col != 0 {
+            write!(f, ":{}", self.col)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+fn main() {
+    let dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("content"));
+    let mut problems = vec![];
+
+    let dialog_path = dir.join("dialog.txt");
+    let dialogs = std::fs::read_to_string(&dialog_path)
+        .map_err(|e| (0, 0, format!("couldn't read dialog file: {e}")))
+        .and_then(|src| Dialogs::parse(&src).map_err(|e| (e.line(), e.col(), e.message())));
+    let dialogs = match dialogs {
+        Ok(dialogs) => {
+            problems.extend(lint_markup(&dialog_path, &dialogs));
+            Some(dialogs)
+        }
+        Err((line, col, message)) => {
+            problems.push(Problem {
+                file: dialog_path.clone(),
+                line,
+                col,
+                message,
+            });
+            None
+        }
+    };
+
+    let enemies_path = dir.join("enemies.txt");
+    let enemies = std::fs::read_to_string(&enemies_path)
+        .map_err(|e| (0, 0, format!("couldn't read enemies file: {e}")))
+        .and_then(|src| Enemies::parse(&src).map_err(|e| (e.line(), e.col(), e.message())));
+    let enemies = match enemies {
+        Ok(enemies) => Some(enemies),
+        Err((line, col, message)) => {
+            problems.push(Problem {
+                file: enemies_path,
+                line,
+                col,
+                message,
+            });
+            None
+        }
+    };
+    if let (Some(dialogs), Some(enemies)) = (&dialogs, &enemies) {
+        problems.extend(lint_battles(&dialog_path, dialogs, enemies));
+    }
+
+    let items_path = dir.join("items.txt");
+    let items = std::fs::read_to_string(&items_path)
+        .map_err(|e| (0, 0, format!("couldn't read items file: {e}")))
+        .and_then(|src| Items::parse(&src).map_err(|e| (e.line(), e.col(), e.message())));
+    let items = match items {
+        Ok(items) => Some(items),
+        Err((line, col, message)) => {
+            problems.push(Problem {
+                file: items_path,
+                line,
+                col,
+                message,
+            });
+            None
+        }
+    };
+    if let (Some(dialogs), Some(items)) = (&dialogs, &items) {
+        problems.extend(lint_items(&dialog_path, dialogs, items));
+    }
+
+    let classes_path = dir.join("classes.txt");
+    let classes = std::fs::read_to_string(&classes_path)
+        .map_err(|e| (0, 0, format!("couldn't read classes file: {e}")))
+        .and_then(|src| {
+            // past the end of the file is where a missing class would go
+            let end = src.lines().count() + 1;
+            Classes::parse(&src)
+                .map(|classes| (classes, end))
+                .map_err(|e| (e.line(), e.col(), e.message()))
+        });
+    match classes {
+        Ok((classes, end)) => {
+            if !classes.contains("hero") {
+                problems.push(Problem {
+                    file: classes_path,
+                    line: end,
+                    col: 0,
+                    message: "there's no hero class for the party to start with".to_string(),
+                });
+            }
+            if let Some(dialogs) = &dialogs {
+                problems.extend(lint_joins(&dialog_path, dialogs, &classes));
+            }
+        }
+        Err((line, col, message)) => problems.push(Problem {
+            file: classes_path,
+            line,
+            col,
+            message,
+        }),
+    }
+
+    let world_path = dir.join("world.txt");
+    let world = std::fs::read_to_string(&world_path)
+        .map_err(|e| (0, 0, format!("couldn't read world manifest: {e}")))
+        .and_then(|src| World::parse(&src).map_err(|e| (e.line(), e.col(), e.message())));
+    let world = match world {
+        Ok(world) => world,
+        Err((line, col, message)) => {
+            problems.push(Problem {
+                file: world_path,
+                line,
+                col,
+                message,
+            });
+            report(&problems);
+            return;
+        }
+    };
+
+    let mut levels = vec![];
+    for (asset, line) in world.levels().iter().zip(world.level_lines()) {
+        let path = dir.join(format!("{asset}.txt"));
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|e| (0, 0, format!("couldn't read level file: {e}")))
+            .and_then(|src| Level::parse(&src).map_err(|e| (e.line(), e.col(), e.message())));
+        match parsed {
+            Ok(level) => levels.push((path, level)),
+            Err((0, _, message)) => problems.push(Problem {
+                file: world_path.clone(),
+                line: *line,
+                col: 0,
+                message: format!("level {asset}: {message}"),
+            }),
+            Err((line, col, message)) => problems.push(Problem {
+                file: path,
+                line,
+                col,
+                message,
+            }),
+        }
+    }
+    for path in level_paths(&dir) {
+        let listed = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| world.levels().iter().any(|asset| asset == stem));
+        if !listed {
+            problems.push(Problem {
+                file: path,
+                line: 1,
+                col: 0,
+                message: "level file isn't listed in world.txt".to_string(),
+            });
+        }
+    }
+    match levels
+        .iter()
+        .find(|(_, level)| level.name() == world.start())
+    {
+        None => problems.push(Problem {
+            file: world_path.clone(),
+            line: world.start_line(),
+            col: 0,
+            message: format!("starting level {} isn't in the world", world.start()),
+        }),
+        Some((path, level)) => {
+            if !level
+                .starts()
+                .iter()
+                .any(|(etype, _)| *etype == EntityType::Player)
+            {
+                problems.push(Problem {
+                    file: path.clone(),
+                    line: level.starts_end(),
+                    col: 0,
+                    message: "starting level has no player start".to_string(),
+                });
+            }
+        }
+    }
+
+    problems.extend(lint_levels(
+        &levels,
+        dialogs.as_ref(),
+        enemies.as_ref(),
+        items.as_ref(),
+    ));
+    report(&problems);
+    println!("{} level(s) OK", levels.len());
+}
+
+// Prints every problem, exiting with an error status if there were any
+fn report(problems: &[Problem]) {
+    for problem in problems.iter() {
+        println!("{problem}");
+    }
+    if !problems.is_empty() {
+        println!("{} problem(s) found", problems.len());
+        std::process::exit(1);
+    }
+}
+
+fn level_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.starts_with("level") && name.ends_with(".txt")
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn describe(etype: &EntityType) -> &'static str {
+    match etype {
+        EntityType::Player => "player start",
+        EntityType::Npc(_) => "npc",
+        EntityType::Door(..) => "door",
+        EntityType::Enemy(_) => "enemy",
+        EntityType::Chest(..) => "chest",
+    }
+}
+
+fn lint_battles(path: &Path, dialogs: &Dialogs, enemies: &Enemies) -> Vec<Problem> {
+    let mut problems = vec![];
+    for (id, dlg) in dialogs.iter() {
+        for (effect, line) in dlg.effects.iter().zip(&dlg.effect_lines) {
+            if let Effect::Battle(group) = effect {
+                if !enemies.has_group(group) {
+                    problems.push(Problem {
+                        file: path.to_path_buf(),
+                        line: *line,
+                        col: 0,
+                        message: format!(
+                            "dialog {id} starts a battle with {group}, which isn't in enemies.txt"
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn lint_items(path: &Path, dialogs: &Dialogs, items: &Items) -> Vec<Problem> {
+    let mut problems = vec![];
+    for (id, dlg) in dialogs.iter() {
+        for (effect, line) in dlg.effects.iter().zip(&dlg.effect_lines) {
+            let (verb, item) = match effect {
+                Effect::Give(item, _) => ("gives", item),
+                Effect::Take(item, _) => ("takes", item),
+                _ => continue,
+            };
+            if !items.contains(item) {
+                problems.push(Problem {
+                    file: path.to_path_buf(),
+                    line: *line,
+                    col: 0,
+                    message: format!("dialog {id} {verb} {item}, which isn't in items.txt"),
+                });
+            }
+        }
+        for (page, line) in dlg.pages.iter().zip(&dlg.page_lines) {
+            // broken markup is lint_markup's problem
+            let counted =
+                markup::parse(page)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|piece| match piece {
+                        Markup::Var(Var::Count(item)) => Some(item),
+                        _ => None,
+                    });
+            for item in counted {
+                if !items.contains(&item) {
+                    problems.push(Problem {
+                        file: path.to_path_buf(),
+                        line: *line,
+                        col: 0,
+                        message: format!("dialog {id} counts {item}, which isn't in items.txt"),
+                    });
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn lint_markup(path: &Path, dialogs: &Dialogs) -> Vec<Problem> {
+    let mut problems = vec![];
+    for (id, dlg) in dialogs.iter() {
+        for (page, (text, line)) in dlg.pages.iter().zip(&dlg.page_lines).enumerate() {
+            if let Err(e) = markup::parse(text) {
+                problems.push(Problem {
+                    file: path.to_path_buf(),
+                    line: *line,
+                    col: 0,
+                    message: format!(
+                        "dialog {id} page {}, column {}: {}",
+                        page + 1,
+                        e.col(),
+                        e.message()
+                    ),
+                });
+            }
+        }
+    }
+    problems
+}
+
+fn lint_joins(path: &Path, dialogs: &Dialogs, classes: &Classes) -> Vec<Problem> {
+    let mut problems = vec![];
+    for (id, dlg) in dialogs.iter() {
+        for (effect, line) in dlg.effects.iter().zip(&dlg.effect_lines) {
+            if let Effect::Join(class, name) = effect {
+                if !classes.contains(class) {
+                    problems.push(Problem {
+                        file: path.to_path_buf(),
+                        line: *line,
+                        col: 0,
+                        message: format!(
+                            "dialog {id} has {name} join as a {class}, which isn't in classes.txt"
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn lint_levels(
+    levels: &[(PathBuf, Level)],
+    dialogs: Option<&Dialogs>,
+    enemies: Option<&Enemies>,
+    items: Option<&Items>,
+) -> Vec<Problem> {
+    let mut problems = vec![];
+    for (idx, (path, level)) in levels.iter().enumerate() {
+        if let Some((other, _)) = levels[..idx]
+            .iter()
+            .find(|(_, other)| other.name() == level.name())
+        {
+            problems.push(Problem {
+                file: path.clone(),
+                line: 1,
+                col: 1,
+                message: format!(
+                    "level name {} is already used by {}",
+                    level.name(),
+                    other.display()
+                ),
+            });
+        }
+        for ((etype, pos), line) in level.starts().iter().zip(level.start_lines()) {
+            let mut report = |message: String| {
+                problems.push(Problem {
+                    file: path.clone(),
+                    line: *line,
+                    col: 0,
+                    message,
+                })
+            };
+            match level.get_tile(*pos) {
+                None => report(format!(
+                    "{} at ({}, {}) is outside of the {}x{} map",
+                    describe(etype),
+                    pos.x,
+                    pos.y,
+                    level.width(),
+                    level.height()
+                )),
+                Some(tile) if tile.flags.blocks() => report(format!(
+                    "{} at ({}, {}) is on a tile nobody can stand on",
+                    describe(etype),
+                    pos.x,
+                    pos.y
+                )),
+                Some(_) => {}
+            }
+            match etype {
+                EntityType::Player => {}
+                EntityType::Npc(dlg) => {
+                    if dialogs.is_some_and(|dialogs| !dialogs.contains(*dlg)) {
+                        report(format!("npc dialog {dlg} isn't in dialog.txt"));
+                    }
+                }
+                EntityType::Enemy(group) => {
+                    if enemies.is_some_and(|enemies| !enemies.has_group(group)) {
+                        report(format!("enemy group {group} isn't in enemies.txt"));
+                    }
+                }
+                EntityType::Chest(item, count) => {
+                    if items.is_some_and(|items| !items.contains(item)) {
+                        report(format!("chest item {item} isn't in items.txt"));
+                    }
+                    if *count == 0 {
+                        report("chest has to hold at least 1 item".to_string());
+                    }
+                }
+                EntityType::Door(to, x, y) => {
+                    let dest = Vec2 { x: *x, y: *y };
+                    match levels.iter().find(|(_, l)| l.name() == to) {
+                        None => report(format!("door leads to nonexistent level {to}")),
+                        Some((_, to_level)) => match to_level.get_tile(dest) {
+                            None => report(format!(
+                                "door destination ({x}, {y}) is outside of {to}'s {}x{} map",
+                                to_level.width(),
+                                to_level.height()
+                            )),
+                            Some(tile) if tile.flags.blocks() => report(format!(
+                                "door destination ({x}, {y}) is on a tile nobody can stand on in {to}"
+                            )),
+                            Some(_) => {}
+                        },
+                    }
+                }
+            }
+        }
+        for (zone, line) in level.encounters().iter().zip(level.encounter_lines()) {
+            let mut report = |message: String| {
+                problems.push(Problem {
+                    file: path.clone(),
+                    line: *line,
+                    col: 0,
+                    message,
+                })
+            };
+            let area = zone.area;
+            if area.w == 0
+                || area.h == 0
+                || area.x as usize + area.w as usize > level.width()
+                || area.y as usize + area.h as usize > level.height()
+            {
+                report(format!(
+                    "encounter zone at ({}, {}) sized {}x{} doesn't fit in the {}x{} map",
+                    area.x,
+                    area.y,
+                    area.w,
+                    area.h,
+                    level.width(),
+                    level.height()
+                ));
+            }
+            if zone.rate == 0 {
+                report("encounter rate has to be at least 1".to_string());
+            }
+            for (group, _) in zone.groups.iter() {
+                if enemies.is_some_and(|enemies| !enemies.has_group(group)) {
+                    report(format!("enemy group {group} isn't in enemies.txt"));
+                }
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_lint_battles() {
+        let dialogs =
+            Dialogs::parse("dialog 0 A\npage hi\nbattle bats\ndialog 1 B\npage go\nbattle rats\n")
+                .unwrap();
+        let enemies = Enemies::parse("enemy rat 0 0 1 0 1 1 1 Rat\ngroup rats rat rat\n").unwrap();
+        let problems: Vec<String> = lint_battles(Path::new("dialog.txt"), &dialogs, &enemies)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec!["dialog.txt:3: dialog 0 starts a battle with bats, which isn't in enemies.txt"]
+        );
+    }
+    #[test]
+    fn test_lint_items() {
+        let dialogs = Dialogs::parse(
+            "dialog 0 A\npage hi\ngive potion 1\ntake gem 2\ndialog 1 B\npage go\ngive rock 1\npage {count gem} and {count ore}\n",
+        )
+        .unwrap();
+        let items = Items::parse("item potion consumable Potion\nitem gem key Gem\n").unwrap();
+        let problems: Vec<String> = lint_items(Path::new("dialog.txt"), &dialogs, &items)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "dialog.txt:7: dialog 1 gives rock, which isn't in items.txt",
+                "dialog.txt:8: dialog 1 counts ore, which isn't in items.txt"
+            ]
+        );
+    }
+    #[test]
+    fn test_lint_markup() {
+        let dialogs = Dialogs::parse(
+            "dialog 0 A\npage hi {player_name}\npage you have {nope} of those\ndialog 1 B\npage *oops\n",
+        )
+        .unwrap();
+        let problems: Vec<String> = lint_markup(Path::new("dialog.txt"), &dialogs)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "dialog.txt:3: dialog 0 page 2, column 10: unknown variable nope",
+                "dialog.txt:5: dialog 1 page 1, column 1: this * highlight is never closed"
+            ]
+        );
+    }
+    #[test]
+    fn test_lint_joins() {
+        let dialogs =
+            Dialogs::parse("dialog 0 A\npage hi\njoin mage Mira\njoin thief Kit\n").unwrap();
+        let classes =
+            Classes::parse("class mage Mage\nbase 18 12 3 2 5\nxp 12\nsprite 17 612\n").unwrap();
+        let problems: Vec<String> = lint_joins(Path::new("dialog.txt"), &dialogs, &classes)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec!["dialog.txt:4: dialog 0 has Kit join as a thief, which isn't in classes.txt"]
+        );
+    }
+    #[test]
+    fn test_lint_levels() {
+        let room = |name: &str, starts: &str| {
+            let src = format!(
+                "{name} 4 2\n===\n. o 85 0 16 16\n# s 289 357 16 16\n~ w 34 0 16 16\n= c 51 0 16 16\n===\n. . # ~\n# . . =\n===\n{starts}"
+            );
+            (
+                PathBuf::from(format!("{name}.txt")),
+                Level::parse(&src).unwrap(),
+            )
+        };
+        let clean = vec![
+            room(
+                "a",
+                "player 0 0\nnpc 1 1 1\ndoor b 1 0 2 1\nenemy rats 1 0\n",
+            ),
+            room("b", "door a 0 0 1 0\nchest potion 3 1 1\ndoor a 3 0 3 0\n"),
+        ];
+        let dialogs = Dialogs::parse("dialog 0 A\npage hi\ndialog 1 B\npage bye\n").unwrap();
+        let enemies = Enemies::parse("enemy rat 0 0 1 0 1 1 1 Rat\ngroup rats rat rat\n").unwrap();
+        let items = Items::parse("item potion consumable Potion\n").unwrap();
+        assert!(lint_levels(&clean, Some(&dialogs), Some(&enemies), Some(&items)).is_empty());
+
+        let broken = vec![
+            room(
+                "a",
+                "player 2 0\nnpc 2 1 1\ndoor c 1 0 2 1\nenemy bats 1 1\nchest gem 1 1 0\nchest potion 0 2 1\nnpc 0 3 1\n",
+            ),
+            room(
+                "b",
+                "door a 5 0 1 0\ndoor a 0 1 1 1\ndoor a 3 1 1 1\nencounter 2 0 3 2 0 rats 1 bats 1\n",
+            ),
+        ];
+        let problems: Vec<String> =
+            lint_levels(&broken, Some(&dialogs), Some(&enemies), Some(&items))
+                .iter()
+                .map(|p| p.to_string())
+                .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "a.txt:11: player start at (2, 0) is on a tile nobody can stand on",
+                "a.txt:12: npc dialog 2 isn't in dialog.txt",
+                "a.txt:13: door leads to nonexistent level c",
+                "a.txt:14: enemy group bats isn't in enemies.txt",
+                "a.txt:15: chest item gem isn't in items.txt",
+                "a.txt:16: chest has to hold at least 1 item",
+                "a.txt:17: npc at (3, 1) is on a tile nobody can stand on",
+                "b.txt:11: door destination (5, 0) is outside of a's 4x2 map",
+                "b.txt:12: door destination (0, 1) is on a tile nobody can stand on in a",
+                "b.txt:13: door destination (3, 1) is on a tile nobody can stand on in a",
+                "b.txt:14: encounter zone at (2, 0) sized 3x2 doesn't fit in the 4x2 map",
+                "b.txt:14: encounter rate has to be at least 1",
+                "b.txt:14: enemy group bats isn't in enemies.txt",
+            ]
+        );
+    }
+}
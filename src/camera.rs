@@ -0,0 +1,204 @@
+use crate::geom::Rect;
+use frenderer::sprites::Camera2D;
+
+/// A view onto the level that follows something around (usually the player).
+///
+/// Positions are in world pixels with y going up, the way sprites are placed: the view's
+/// `pos` is its bottom left corner.  The view never shows anything past the edges of the
+/// level, unless the level is smaller than the view, in which case the level sits in the
+/// middle of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pos: [f32; 2],
+    size: [f32; 2],
+    // how far the target can wander from the middle of the view before the view moves
+    dead_zone: [f32; 2],
+    // how much of the way to where it's going the view gets each second, or None to jump there
+    smoothing: Option<f32>,
+}
+
+impl Camera {
+    pub fn new(w: f32, h: f32) -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            size: [w, h],
+            dead_zone: [0.0, 0.0],
+            smoothing: None,
+        }
+    }
+    /// Lets the target move around a `w` by `h` box in the middle of the view without the
+    /// view moving
+    pub fn with_dead_zone(self, w: f32, h: f32) -> Self {
+        Self {
+            dead_zone: [w, h],
+            ..self
+        }
+    }
+    /// Glides the view towards where it should be instead of jumping there.  `rate` is
+    /// roughly how many times a second it closes the distance; higher is snappier.
+    pub fn with_smoothing(self, rate: f32) -> Self {
+        Self {
+            smoothing: Some(rate),
+            ..self
+        }
+    }
+    pub fn pos(&self) -> [f32; 2] {
+        self.pos
+    }
+    pub fn size(&self) -> [f32; 2] {
+        self.size
+    }
+    // Where the view wants to be to keep `target` in the dead zone, inside a level `bounds` big
+    fn goal(&self, target: [f32; 2], bounds: [f32; 2]) -> [f32; 2] {
+        std::array::from_fn(|i| {
+            let (pos, size, dead) = (self.pos[i], self.size[i], self.dead_zone[i]);
+            if bounds[i] <= size {
+                return (bounds[i] - size) / 2.0;
+            }
+            let center = pos + size / 2.0;
+            let center = center.clamp(target[i] - dead / 2.0, target[i] + dead / 2.0);
+            (center - size / 2.0).clamp(0.0, bounds[i] - size)
+        })
+    }
+    /// Jumps straight to `target`, e.g. on entering a level
+    pub fn snap(&mut self, target: [f32; 2], bounds: [f32; 2]) {
+        // with no history to go on, put the target right in the middle
+        self.pos = std::array::from_fn(|i| target[i] - self.size[i] / 2.0);
+        self.pos = self.goal(target, bounds);
+    }
+    /// Moves towards `target` over `dt` seconds
+    pub fn follow(&mut self, target: [f32; 2], bounds: [f32; 2], dt: f32) {
+        let goal = self.goal(target, bounds);
+        let Some(rate) = self.smoothing else {
+            self.pos = goal;
+            return;
+        };
+        let t = 1.0 - (-rate * dt).exp();
+        for (pos, goal) in self.pos.iter_mut().zip(goal) {
+            *pos += (goal - *pos) * t;
+            // don't creep along forever by fractions of a pixel
+            if (goal - *pos).abs() < 0.05 {
+                *pos = goal;
+            }
+        }
+    }
+    /// The view for the renderer, snapped to whole pixels so tiles don't shimmer
+    pub fn camera2d(&self) -> Camera2D {
+        Camera2D {
+            screen_pos: self.pos.map(f32::round),
+            screen_size: self.size,
+        }
+    }
+    /// The tiles of a `w` by `h` grid of `tile_sz` pixel tiles (row 0 at the top) that are
+    /// in view, plus `margin` more on each side, cut off at the edges of the grid
+    pub fn visible_tiles(&self, tile_sz: usize, w: usize, h: usize, margin: usize) -> Rect {
+        let tile_sz = tile_sz as f32;
+        let margin = margin as i32;
+        let span = |lo: f32, len: f32, max: usize| {
+            let first = (lo / tile_sz).floor() as i32 - margin;
+            let last = ((lo + len) / tile_sz).ceil() as i32 + margin;
+            (first.clamp(0, max as i32), last.clamp(0, max as i32))
+        };
+        let (x0, x1) = span(self.pos[0], self.size[0], w);
+        // counted up from the bottom of the level, so flip them over to get rows
+        let (b0, b1) = span(self.pos[1], self.size[1], h);
+        Rect {
+            x: x0 as u16,
+            y: (h as i32 - b1) as u16,
+            w: (x1 - x0) as u16,
+            h: (b1 - b0) as u16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_clamp() {
+        let mut cam = Camera::new(320.0, 240.0);
+        cam.snap([500.0, 300.0], [1000.0, 800.0]);
+        assert_eq!(cam.pos(), [340.0, 180.0]);
+        // can't see past the bottom left...
+        cam.snap([8.0, 8.0], [1000.0, 800.0]);
+        assert_eq!(cam.pos(), [0.0, 0.0]);
+        // ...or the top right
+        cam.snap([992.0, 792.0], [1000.0, 800.0]);
+        assert_eq!(cam.pos(), [680.0, 560.0]);
+        // a small level sits in the middle of the screen
+        cam.snap([8.0, 300.0], [160.0, 800.0]);
+        assert_eq!(cam.pos(), [-80.0, 180.0]);
+        assert_eq!(cam.camera2d().screen_pos, [-80.0, 180.0]);
+    }
+    #[test]
+    fn test_follow() {
+        let bounds = [1000.0, 800.0];
+        let mut cam = Camera::new(320.0, 240.0).with_dead_zone(64.0, 32.0);
+        cam.snap([500.0, 400.0], bounds);
+        assert_eq!(cam.pos(), [340.0, 280.0]);
+        // inside the dead zone nothing moves
+        cam.follow([530.0, 412.0], bounds, 0.1);
+        assert_eq!(cam.pos(), [340.0, 280.0]);
+        // past it, the view moves just enough to keep the target at its edge
+        cam.follow([550.0, 380.0], bounds, 0.1);
+        assert_eq!(cam.pos(), [358.0, 276.0]);
+
+        let mut cam = Camera::new(320.0, 240.0).with_smoothing(10.0);
+        cam.snap([500.0, 400.0], bounds);
+        cam.follow([516.0, 400.0], bounds, 1.0 / 60.0);
+        let [x, y] = cam.pos();
+        assert!(x > 340.0 && x < 356.0, "{x}");
+        assert_eq!(y, 280.0);
+        for _ in 0..120 {
+            cam.follow([516.0, 400.0], bounds, 1.0 / 60.0);
+        }
+        assert_eq!(cam.pos(), [356.0, 280.0]);
+    }
+    #[test]
+    fn test_visible_tiles() {
+        let mut cam = Camera::new(320.0, 240.0);
+        // exactly one screen
+        cam.snap([160.0, 120.0], [320.0, 240.0]);
+        assert_eq!(
+            cam.visible_tiles(16, 20, 15, 1),
+            Rect {
+                x: 0,
+                y: 0,
+                w: 20,
+                h: 15
+            }
+        );
+        // the top left corner of a 40x30 level, partway into a tile
+        cam.snap([168.0, 360.0], [640.0, 480.0]);
+        assert_eq!(cam.pos(), [8.0, 240.0]);
+        assert_eq!(
+            cam.visible_tiles(16, 40, 30, 0),
+            Rect {
+                x: 0,
+                y: 0,
+                w: 21,
+                h: 15
+            }
+        );
+        assert_eq!(
+            cam.visible_tiles(16, 40, 30, 2),
+            Rect {
+                x: 0,
+                y: 0,
+                w: 23,
+                h: 17
+            }
+        );
+        // the bottom right corner
+        cam.snap([640.0, 0.0], [640.0, 480.0]);
+        assert_eq!(
+            cam.visible_tiles(16, 40, 30, 1),
+            Rect {
+                x: 19,
+                y: 14,
+                w: 21,
+                h: 16
+            }
+        );
+    }
+}
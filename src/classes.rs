@@ -0,0 +1,440 @@
+use crate::battle::{skill_named, Stats};
+use crate::parse::{FieldError, Fields};
+use crate::TILE_SZ;
+use frenderer::sprites::SheetRegion;
+use std::collections::HashMap;
+
+/*
+Party classes and how they grow are read from an ad hoc format like this:
+
+class ID NAME
+base HP MP ATTACK DEFENSE SPEED
+growth HP MP ATTACK DEFENSE SPEED
+xp AMOUNT
+skills SKILL SKILL
+sprite SHEET-X SHEET-Y
+
+The base line is the class's stats at level 1.  Growth is how much each stat goes up
+per level, in tenths of a point, so 15 means three points every two levels.  It takes
+AMOUNT XP (at least 1) to get from level 1 to level 2, and every level after that takes AMOUNT more
+than the one before it.  SHEET-X and SHEET-Y are the top left of the 16x16 sprite
+members of the class have on the map and in battle.  The lines after a class line
+describe that class; base, xp and sprite are required, and a class with no growth line
+stays the same forever.
+Blank lines and lines starting with # are ignored.
+*/
+
+/// Nobody levels up past this
+pub const MAX_LEVEL: u32 = 99;
+
+#[derive(Debug, Clone)]
+pub struct ClassDef {
+    pub name: String,
+    pub base: Stats,
+    // tenths of a point per level
+    pub growth: Stats,
+    pub xp: u32,
+    pub skills: Vec<usize>,
+    pub sprite: SheetRegion,
+}
+
+impl ClassDef {
+    /// Stats for someone of this class at the given level
+    pub fn stats_at(&self, level: u32) -> Stats {
+        let steps = level.clamp(1, MAX_LEVEL) - 1;
+        let grow = |base: u32, growth: u32| base.saturating_add(growth.saturating_mul(steps) / 10);
+        Stats {
+            max_hp: grow(self.base.max_hp, self.growth.max_hp),
+            max_mp: grow(self.base.max_mp, self.growth.max_mp),
+            attack: grow(self.base.attack, self.growth.attack),
+            defense: grow(self.base.defense, self.growth.defense),
+            speed: grow(self.base.speed, self.growth.speed),
+        }
+    }
+    /// Total XP needed to reach the given level from level 1
+    pub fn xp_for(&self, level: u32) -> u32 {
+        let level = level.clamp(1, MAX_LEVEL);
+        self.xp.saturating_mul(level * (level - 1) / 2)
+    }
+    /// The level someone of this class is at with this much XP in total
+    pub fn level_for(&self, xp: u32) -> u32 {
+        (1..MAX_LEVEL)
+            .find(|&level| xp < self.xp_for(level + 1))
+            .unwrap_or(MAX_LEVEL)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Classes {
+    classes: HashMap<String, ClassDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassesParseError {
+    Field(FieldError),
+    UnknownKeyword {
+        line: usize,
+        col: usize,
+        keyword: String,
+    },
+    OutsideClass {
+        line: usize,
+        col: usize,
+    },
+    DuplicateId {
+        line: usize,
+        col: usize,
+        id: String,
+    },
+    UnknownSkill {
+        line: usize,
+        col: usize,
+        skill: String,
+    },
+    // the class line of a class with no base, xp or sprite line
+    Incomplete {
+        line: usize,
+        col: usize,
+        id: String,
+        missing: &'static str,
+    },
+}
+
+impl ClassesParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Field(err) => err.line(),
+            Self::UnknownKeyword { line, .. }
+            | Self::OutsideClass { line, .. }
+            | Self::DuplicateId { line, .. }
+            | Self::UnknownSkill { line, .. }
+            | Self::Incomplete { line, .. } => *line,
+        }
+    }
+    pub fn col(&self) -> usize {
+        match self {
+            Self::Field(err) => err.col(),
+            Self::UnknownKeyword { col, .. }
+            | Self::OutsideClass { col, .. }
+            | Self::DuplicateId { col, .. }
+            | Self::UnknownSkill { col, .. }
+            | Self::Incomplete { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::Field(err) => err.message(),
+            Self::UnknownKeyword { keyword, .. } => {
+                format!("expected class, base, growth, xp, skills or sprite, not {keyword:?}")
+            }
+            Self::OutsideClass { .. } => "this needs to come after a class line".to_string(),
+            Self::DuplicateId { id, .. } => format!("class {id} is already defined"),
+            Self::UnknownSkill { skill, .. } => format!("there's no skill called {skill:?}"),
+            Self::Incomplete { id, missing, .. } => format!("class {id} has no {missing} line"),
+        }
+    }
+}
+
+impl std::fmt::Display for ClassesParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for ClassesParseError {}
+
+impl From<FieldError> for ClassesParseError {
+    fn from(err: FieldError) -> Self {
+        Self::Field(err)
+    }
+}
+
+fn stats(fields: &mut Fields) -> Result<Stats, FieldError> {
+    Ok(Stats {
+        max_hp: fields.num("HP")?,
+        max_mp: fields.num("MP")?,
+        attack: fields.num("attack")?,
+        defense: fields.num("defense")?,
+        speed: fields.num("speed")?,
+    })
+}
+
+impl Classes {
+    pub fn parse(s: &str) -> Result<Self, ClassesParseError> {
+        let mut classes = HashMap::new();
+        // where each class was defined, and which of its required lines haven't come up yet
+        let mut defined = vec![];
+        let mut last_class: Option<String> = None;
+        for (line_no, line) in s.lines().enumerate() {
+            let line_no = line_no + 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut fields = Fields::new(line_no, line);
+            let (col, keyword) = fields.chunk("class, base, growth, xp, skills or sprite")?;
+            if keyword == "class" {
+                let (id_col, id) = fields.chunk("class id")?;
+                let class = ClassDef {
+                    name: fields.rest("class name")?.1.to_string(),
+                    base: Stats::default(),
+                    growth: Stats::default(),
+                    xp: 0,
+                    skills: vec![],
+                    sprite: SheetRegion::ZERO,
+                };
+                if classes.insert(id.to_string(), class).is_some() {
+                    return Err(ClassesParseError::DuplicateId {
+                        line: line_no,
+                        col: id_col,
+                        id: id.to_string(),
+                    });
+                }
+                defined.push((
+                    line_no,
+                    id_col,
+                    id.to_string(),
+                    vec!["base", "xp", "sprite"],
+                ));
+                last_class = Some(id.to_string());
+                continue;
+            }
+            let (Some(class), Some((_, _, _, missing))) = (
+                last_class.as_ref().and_then(|id| classes.get_mut(id)),
+                defined.last_mut(),
+            ) else {
+                return Err(match keyword {
+                    "base" | "growth" | "xp" | "skills" | "sprite" => {
+                        ClassesParseError::OutsideClass { line: line_no, col }
+                    }
+                    _ => ClassesParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    },
+                });
+            };
+            match keyword {
+                "base" => class.base = stats(&mut fields)?,
+                "growth" => class.growth = stats(&mut fields)?,
+                "xp" => {
+                    // with no XP between levels, everyone would go straight to the top
+                    let (col, text) = fields.chunk("xp amount")?;
+                    class.xp = text.parse().ok().filter(|&xp| xp > 0).ok_or_else(|| {
+                        FieldError::BadNumber {
+                            line: line_no,
+                            col,
+                            field: "xp amount",
+                            text: text.to_string(),
+                        }
+                    })?;
+                }
+                "sprite" => {
+                    let x = fields.num::<u16>("sheet x")?;
+                    let y = fields.num::<u16>("sheet y")?;
+                    class.sprite = SheetRegion::new(0, x, y, 14, TILE_SZ as i16, TILE_SZ as i16);
+                }
+                "skills" => {
+                    for (col, skill) in &mut fields {
+                        let Some(idx) = skill_named(skill) else {
+                            return Err(ClassesParseError::UnknownSkill {
+                                line: line_no,
+                                col,
+                                skill: skill.to_string(),
+                            });
+                        };
+                        class.skills.push(idx);
+                    }
+                }
+                _ => {
+                    return Err(ClassesParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    })
+                }
+            }
+            fields.finish()?;
+            missing.retain(|&required| required != keyword);
+        }
+        for (line, col, id, missing) in defined {
+            if let Some(&missing) = missing.first() {
+                return Err(ClassesParseError::Incomplete {
+                    line,
+                    col,
+                    id,
+                    missing,
+                });
+            }
+        }
+        Ok(Self { classes })
+    }
+    pub fn get(&self, id: &str) -> Option<&ClassDef> {
+        self.classes.get(id)
+    }
+    pub fn contains(&self, id: &str) -> bool {
+        self.classes.contains_key(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const SAMPLE: &str = "# a comment
+class knight Knight
+base 30 0 8 5 3
+growth 25 0 15 10 5
+xp 10
+sprite 0 629
+
+class mage Mage
+base 18 12 3 2 5
+xp 12
+skills fire heal
+sprite 17 612
+";
+    #[test]
+    fn test_parse_classes() {
+        let classes = Classes::parse(SAMPLE).unwrap();
+        let knight = classes.get("knight").unwrap();
+        assert_eq!(knight.name, "Knight");
+        assert_eq!(
+            knight.growth,
+            Stats {
+                max_hp: 25,
+                max_mp: 0,
+                attack: 15,
+                defense: 10,
+                speed: 5
+            }
+        );
+        assert!(knight.skills.is_empty());
+        let mage = classes.get("mage").unwrap();
+        assert_eq!(
+            mage.skills,
+            vec![skill_named("fire").unwrap(), skill_named("heal").unwrap()]
+        );
+        assert_eq!(mage.stats_at(50), mage.base);
+        assert_eq!((mage.sprite.x, mage.sprite.y), (17, 612));
+        assert!(!classes.contains("thief"));
+    }
+    #[test]
+    fn test_growth() {
+        let classes = Classes::parse(SAMPLE).unwrap();
+        let knight = classes.get("knight").unwrap();
+        assert_eq!(knight.stats_at(1), knight.base);
+        // fractional growth rounds down, so it builds up over a few levels
+        assert_eq!(
+            knight.stats_at(2),
+            Stats {
+                max_hp: 32,
+                max_mp: 0,
+                attack: 9,
+                defense: 6,
+                speed: 3
+            }
+        );
+        assert_eq!(
+            knight.stats_at(3),
+            Stats {
+                max_hp: 35,
+                max_mp: 0,
+                attack: 11,
+                defense: 7,
+                speed: 4
+            }
+        );
+        assert_eq!(knight.stats_at(0), knight.stats_at(1));
+        assert_eq!(knight.stats_at(500), knight.stats_at(MAX_LEVEL));
+        // absurd growth tops out instead of overflowing
+        let classes = Classes::parse(&SAMPLE.replace("growth 25", "growth 4000000000")).unwrap();
+        assert_eq!(
+            classes.get("knight").unwrap().stats_at(MAX_LEVEL).max_hp,
+            u32::MAX / 10 + 30
+        );
+    }
+    #[test]
+    fn test_xp_curve() {
+        let knight = Classes::parse(SAMPLE)
+            .unwrap()
+            .get("knight")
+            .unwrap()
+            .clone();
+        assert_eq!(knight.xp_for(1), 0);
+        assert_eq!(knight.xp_for(2), 10);
+        assert_eq!(knight.xp_for(3), 30);
+        assert_eq!(knight.xp_for(4), 60);
+        assert_eq!(knight.level_for(0), 1);
+        assert_eq!(knight.level_for(9), 1);
+        assert_eq!(knight.level_for(10), 2);
+        assert_eq!(knight.level_for(59), 3);
+        assert_eq!(knight.level_for(60), 4);
+        assert_eq!(knight.level_for(u32::MAX), MAX_LEVEL);
+    }
+    #[test]
+    fn test_parse_errors() {
+        let err = |src: &str| Classes::parse(src).unwrap_err();
+        assert_eq!(
+            err(&SAMPLE.replace("fire heal", "fire ice")),
+            ClassesParseError::UnknownSkill {
+                line: 11,
+                col: 13,
+                skill: "ice".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("class mage", "class knight")),
+            ClassesParseError::DuplicateId {
+                line: 8,
+                col: 7,
+                id: "knight".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("xp 10", "xp 0")),
+            ClassesParseError::Field(FieldError::BadNumber {
+                line: 5,
+                col: 4,
+                field: "xp amount",
+                text: "0".to_string()
+            })
+        );
+        assert_eq!(
+            err("xp 10\n"),
+            ClassesParseError::OutsideClass { line: 1, col: 1 }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("xp 12\n", "")),
+            ClassesParseError::Incomplete {
+                line: 8,
+                col: 7,
+                id: "mage".to_string(),
+                missing: "xp"
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("sprite 0 629\n", "")),
+            ClassesParseError::Incomplete {
+                line: 2,
+                col: 7,
+                id: "knight".to_string(),
+                missing: "sprite"
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("growth", "grow")),
+            ClassesParseError::UnknownKeyword {
+                line: 4,
+                col: 1,
+                keyword: "grow".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("base 30 0 8 5 3", "base 30 0 8 5")),
+            ClassesParseError::Field(FieldError::MissingField {
+                line: 3,
+                col: 14,
+                field: "speed"
+            })
+        );
+    }
+}
@@ -0,0 +1,582 @@
+use crate::parse::{FieldError, Fields};
+use std::collections::{HashMap, HashSet};
+
+/*
+Dialog is a graph of numbered nodes, read from an ad hoc format like this:
+
+dialog ID SPEAKER
+page TEXT
+page TEXT
+choice NEXT-ID TEXT
+choice NEXT-ID if FLAG if !FLAG TEXT
+next NEXT-ID
+give ITEM COUNT
+take ITEM COUNT
+set FLAG
+clear FLAG
+battle GROUP
+rebind DIALOG-ID
+join CLASS NAME
+
+Each node shows its pages one at a time.  If it has choices, they're offered on the
+last page and the picked one says which node comes next; otherwise `next` (if given)
+says where to go after the last page.  A NEXT-ID of `end` ends the conversation.
+A choice can have any number of `if FLAG` (or `if !FLAG`) conditions, and is only
+offered when all of them hold.
+TEXT is wrapped to fit the dialog window, and split across more than one window if
+it's too long for one; it can also use \n for a manual line break, and the markup
+described in markup.rs.  Blank lines and lines starting with # are ignored.
+
+The other lines are effects, which happen in order as soon as the node is entered:
+giving or taking items, setting or clearing story flags, lining up a battle against
+an enemy group (which starts, ending the conversation, once the node's last page has
+been read), changing which dialog the NPC who's talking will use from now on, or
+having someone called NAME of class CLASS join the party (if there's room).
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    Give(String, u32),
+    Take(String, u32),
+    Set(String),
+    Clear(String),
+    Battle(String),
+    Rebind(usize),
+    // class id, then name
+    Join(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub flag: String,
+    // whether the flag has to be set (`if FLAG`) or clear (`if !FLAG`)
+    pub set: bool,
+}
+
+impl Condition {
+    /// Reads `FLAG` or `!FLAG`
+    pub fn parse(flag: &str) -> Self {
+        match flag.strip_prefix('!') {
+            Some(flag) => Self {
+                flag: flag.to_string(),
+                set: false,
+            },
+            None => Self {
+                flag: flag.to_string(),
+                set: true,
+            },
+        }
+    }
+    pub fn holds(&self, flags: &HashSet<String>) -> bool {
+        flags.contains(&self.flag) == self.set
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub text: String,
+    // None ends the conversation
+    pub next: Option<usize>,
+    pub conditions: Vec<Condition>,
+}
+
+impl Choice {
+    pub fn available(&self, flags: &HashSet<String>) -> bool {
+        self.conditions.iter().all(|c| c.holds(flags))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dialog {
+    pub speaker: String,
+    pub pages: Vec<String>,
+    pub choices: Vec<Choice>,
+    pub next: Option<usize>,
+    pub effects: Vec<Effect>,
+    // which line of the dialog file each page and effect came from, for error reporting
+    pub page_lines: Vec<usize>,
+    pub effect_lines: Vec<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct Dialogs {
+    nodes: HashMap<usize, Dialog>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DialogParseError {
+    Field(FieldError),
+    UnknownKeyword {
+        line: usize,
+        col: usize,
+        keyword: String,
+    },
+    OutsideDialog {
+        line: usize,
+        col: usize,
+    },
+    DuplicateId {
+        line: usize,
+        col: usize,
+        id: usize,
+    },
+    NoPages {
+        line: usize,
+        col: usize,
+        id: usize,
+    },
+    UnknownTarget {
+        line: usize,
+        col: usize,
+        id: usize,
+    },
+}
+
+impl DialogParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Field(err) => err.line(),
+            Self::UnknownKeyword { line, .. }
+            | Self::OutsideDialog { line, .. }
+            | Self::DuplicateId { line, .. }
+            | Self::NoPages { line, .. }
+            | Self::UnknownTarget { line, .. } => *line,
+        }
+    }
+    pub fn col(&self) -> usize {
+        match self {
+            Self::Field(err) => err.col(),
+            Self::UnknownKeyword { col, .. }
+            | Self::OutsideDialog { col, .. }
+            | Self::DuplicateId { col, .. }
+            | Self::NoPages { col, .. }
+            | Self::UnknownTarget { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::Field(err) => err.message(),
+            Self::UnknownKeyword { keyword, .. } => {
+                format!("expected dialog, page, choice, next or an effect, not {keyword:?}")
+            }
+            Self::OutsideDialog { .. } => "this needs to come after a dialog line".to_string(),
+            Self::DuplicateId { id, .. } => format!("dialog {id} is already defined"),
+            Self::NoPages { id, .. } => format!("dialog {id} has no pages"),
+            Self::UnknownTarget { id, .. } => format!("there's no dialog {id} to go to"),
+        }
+    }
+}
+
+impl std::fmt::Display for DialogParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for DialogParseError {}
+
+impl From<FieldError> for DialogParseError {
+    fn from(err: FieldError) -> Self {
+        Self::Field(err)
+    }
+}
+
+// Reads a NEXT-ID, which is either a dialog number or `end`, and where it was
+fn target(fields: &mut Fields) -> Result<(usize, Option<usize>), FieldError> {
+    match fields.peek() {
+        Some((col, "end")) => {
+            fields.next();
+            Ok((col, None))
+        }
+        Some((col, _)) => Ok((col, Some(fields.num::<usize>("next dialog ID")?))),
+        None => Err(fields.chunk("next dialog ID or end").unwrap_err()),
+    }
+}
+
+impl Dialogs {
+    pub fn parse(s: &str) -> Result<Self, DialogParseError> {
+        let mut nodes: HashMap<usize, Dialog> = HashMap::new();
+        // where each node was declared, and every (line, col, id) that points at a node
+        let mut declared = vec![];
+        let mut targets = vec![];
+        let mut current: Option<usize> = None;
+        let mut line_no = 0;
+        for line in s.lines() {
+            line_no += 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut fields = Fields::new(line_no, line);
+            let (col, keyword) = fields.chunk("dialog, page, choice, next or an effect")?;
+            if keyword == "dialog" {
+                let id_col = fields.peek().map_or(0, |(col, _)| col);
+                let id = fields.num::<usize>("dialog ID")?;
+                if nodes.contains_key(&id) {
+                    return Err(DialogParseError::DuplicateId {
+                        line: line_no,
+                        col: id_col,
+                        id,
+                    });
+                }
+                let speaker = fields.rest("speaker")?.1.to_string();
+                nodes.insert(
+                    id,
+                    Dialog {
+                        speaker,
+                        pages: vec![],
+                        choices: vec![],
+                        next: None,
+                        effects: vec![],
+                        page_lines: vec![],
+                        effect_lines: vec![],
+                    },
+                );
+                declared.push((line_no, col, id));
+                current = Some(id);
+                continue;
+            }
+            let Some(node) = current.and_then(|id| nodes.get_mut(&id)) else {
+                return Err(DialogParseError::OutsideDialog { line: line_no, col });
+            };
+            match keyword {
+                "page" => node.pages.push(fields.rest("page text")?.1.to_string()),
+                "choice" => {
+                    let (target_col, next) = target(&mut fields)?;
+                    let mut conditions = vec![];
+                    while let Some((_, "if")) = fields.peek() {
+                        fields.next();
+                        let flag = fields.chunk("flag")?.1;
+                        conditions.push(Condition::parse(flag));
+                    }
+                    let text = fields.rest("choice text")?.1.to_string();
+                    node.choices.push(Choice {
+                        text,
+                        next,
+                        conditions,
+                    });
+                    targets.extend(next.map(|id| (line_no, target_col, id)));
+                }
+                "next" => {
+                    let (target_col, next) = target(&mut fields)?;
+                    fields.finish()?;
+                    node.next = next;
+                    targets.extend(next.map(|id| (line_no, target_col, id)));
+                }
+                "give" | "take" => {
+                    let item = fields.chunk("item")?.1.to_string();
+                    let count = fields.num::<u32>("item count")?;
+                    fields.finish()?;
+                    node.effects.push(if keyword == "give" {
+                        Effect::Give(item, count)
+                    } else {
+                        Effect::Take(item, count)
+                    });
+                }
+                "set" | "clear" => {
+                    let flag = fields.chunk("flag")?.1.to_string();
+                    fields.finish()?;
+                    node.effects.push(if keyword == "set" {
+                        Effect::Set(flag)
+                    } else {
+                        Effect::Clear(flag)
+                    });
+                }
+                "battle" => {
+                    let group = fields.chunk("enemy group")?.1.to_string();
+                    fields.finish()?;
+                    node.effects.push(Effect::Battle(group));
+                }
+                "rebind" => {
+                    let target_col = fields.peek().map_or(0, |(col, _)| col);
+                    let id = fields.num::<usize>("dialog ID")?;
+                    fields.finish()?;
+                    node.effects.push(Effect::Rebind(id));
+                    targets.push((line_no, target_col, id));
+                }
+                "join" => {
+                    let class = fields.chunk("class")?.1.to_string();
+                    let name = fields.rest("member name")?.1.to_string();
+                    node.effects.push(Effect::Join(class, name));
+                }
+                _ => {
+                    return Err(DialogParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    })
+                }
+            }
+            // whatever page or effect this line added came from here
+            node.page_lines.resize(node.pages.len(), line_no);
+            node.effect_lines.resize(node.effects.len(), line_no);
+        }
+        for (line, col, id) in declared {
+            if nodes[&id].pages.is_empty() {
+                return Err(DialogParseError::NoPages { line, col, id });
+            }
+        }
+        for (line, col, id) in targets {
+            if !nodes.contains_key(&id) {
+                return Err(DialogParseError::UnknownTarget { line, col, id });
+            }
+        }
+        Ok(Self { nodes })
+    }
+    pub fn get(&self, id: usize) -> Option<&Dialog> {
+        self.nodes.get(&id)
+    }
+    pub fn contains(&self, id: usize) -> bool {
+        self.nodes.contains_key(&id)
+    }
+    /// Every node, in order of id
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Dialog)> {
+        let mut ids: Vec<usize> = self.nodes.keys().copied().collect();
+        ids.sort();
+        ids.into_iter().map(|id| (id, &self.nodes[&id]))
+    }
+}
+
+impl std::ops::Index<usize> for Dialogs {
+    type Output = Dialog;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.nodes[&index]
+    }
+}
+
+/// What happened when the player confirmed in a [`Conversation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advance {
+    // moved on to the next page of the same node
+    Page,
+    // moved into a different (or the same) node, starting from its first page
+    Node(usize),
+    // the conversation is over
+    End,
+}
+
+/// Where we are in a dialog graph: which node, which page of it, and which choice is highlighted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conversation {
+    node: usize,
+    page: usize,
+    cursor: usize,
+}
+
+impl Conversation {
+    pub fn new(node: usize) -> Self {
+        Self {
+            node,
+            page: 0,
+            cursor: 0,
+        }
+    }
+    pub fn node(&self) -> usize {
+        self.node
+    }
+    pub fn page(&self) -> usize {
+        self.page
+    }
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+    /// The choices to offer right now, which is only on the last page of a node
+    /// and only those whose conditions hold
+    pub fn choices<'d>(&self, dialogs: &'d Dialogs, flags: &HashSet<String>) -> Vec<&'d Choice> {
+        let dlg = &dialogs[self.node];
+        if self.page + 1 == dlg.pages.len() {
+            dlg.choices.iter().filter(|c| c.available(flags)).collect()
+        } else {
+            vec![]
+        }
+    }
+    /// Moves the choice cursor up or down, wrapping around
+    pub fn move_cursor(&mut self, dialogs: &Dialogs, flags: &HashSet<String>, delta: isize) {
+        let count = self.choices(dialogs, flags).len();
+        if count > 0 {
+            self.cursor = (self.cursor as isize + delta).rem_euclid(count as isize) as usize;
+        }
+    }
+    pub fn confirm(&mut self, dialogs: &Dialogs, flags: &HashSet<String>) -> Advance {
+        let dlg = &dialogs[self.node];
+        let next = if self.page + 1 < dlg.pages.len() {
+            self.page += 1;
+            return Advance::Page;
+        } else if let Some(choice) = self.choices(dialogs, flags).get(self.cursor) {
+            choice.next
+        } else {
+            dlg.next
+        };
+        match next {
+            Some(node) => {
+                *self = Self::new(node);
+                Advance::Node(node)
+            }
+            None => Advance::End,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const SAMPLE: &str = "# a comment
+dialog 0 Old Hermit
+page kind of lonely over here
+page stay a while?
+choice 1 Sure
+choice end No thanks
+choice 2 if met if !angry Hello again
+
+dialog 1 Old Hermit
+page thanks!
+give potion 2
+set met
+rebind 2
+next 2
+dialog 2 Old Hermit
+page bye now
+join mage Mira
+";
+    #[test]
+    fn test_parse_dialogs() {
+        let dialogs = Dialogs::parse(include_str!("../content/dialog.txt")).unwrap();
+        assert!(dialogs.contains(0));
+
+        let dialogs = Dialogs::parse(SAMPLE).unwrap();
+        assert_eq!(
+            dialogs[0],
+            Dialog {
+                speaker: "Old Hermit".to_string(),
+                pages: vec![
+                    "kind of lonely over here".to_string(),
+                    "stay a while?".to_string()
+                ],
+                choices: vec![
+                    Choice {
+                        text: "Sure".to_string(),
+                        next: Some(1),
+                        conditions: vec![]
+                    },
+                    Choice {
+                        text: "No thanks".to_string(),
+                        next: None,
+                        conditions: vec![]
+                    },
+                    Choice {
+                        text: "Hello again".to_string(),
+                        next: Some(2),
+                        conditions: vec![
+                            Condition {
+                                flag: "met".to_string(),
+                                set: true
+                            },
+                            Condition {
+                                flag: "angry".to_string(),
+                                set: false
+                            }
+                        ]
+                    }
+                ],
+                next: None,
+                effects: vec![],
+                page_lines: vec![3, 4],
+                effect_lines: vec![]
+            }
+        );
+        assert_eq!(dialogs[1].next, Some(2));
+        assert_eq!(
+            dialogs[1].effects,
+            vec![
+                Effect::Give("potion".to_string(), 2),
+                Effect::Set("met".to_string()),
+                Effect::Rebind(2)
+            ]
+        );
+        assert_eq!(dialogs[1].effect_lines, vec![11, 12, 13]);
+        assert_eq!(
+            dialogs[2].effects,
+            vec![Effect::Join("mage".to_string(), "Mira".to_string())]
+        );
+        assert!(!dialogs.contains(3));
+
+        let err = |src: &str| Dialogs::parse(src).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err(&SAMPLE.replace("next 2", "next 7")),
+            DialogParseError::UnknownTarget {
+                line: 14,
+                col: 6,
+                id: 7
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("rebind 2", "rebind 9")),
+            DialogParseError::UnknownTarget {
+                line: 13,
+                col: 8,
+                id: 9
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("dialog 2", "dialog 1")),
+            DialogParseError::DuplicateId {
+                line: 15,
+                col: 8,
+                id: 1
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("# a comment", "page too early")),
+            DialogParseError::OutsideDialog { line: 1, col: 1 }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("page bye now", "")),
+            DialogParseError::NoPages {
+                line: 15,
+                col: 1,
+                id: 2
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("choice end", "choice nope")),
+            DialogParseError::Field(FieldError::BadNumber {
+                line: 6,
+                col: 8,
+                field: "next dialog ID",
+                text: "nope".to_string()
+            })
+        );
+    }
+    #[test]
+    fn test_conversation() {
+        let dialogs = Dialogs::parse(SAMPLE).unwrap();
+        let mut flags = HashSet::new();
+        let mut convo = Conversation::new(0);
+        assert!(convo.choices(&dialogs, &flags).is_empty());
+        assert_eq!(convo.confirm(&dialogs, &flags), Advance::Page);
+        assert_eq!(convo.choices(&dialogs, &flags).len(), 2);
+        convo.move_cursor(&dialogs, &flags, -1);
+        assert_eq!(convo.cursor(), 1);
+        convo.move_cursor(&dialogs, &flags, 1);
+        assert_eq!(convo.cursor(), 0);
+        assert_eq!(convo.confirm(&dialogs, &flags), Advance::Node(1));
+        assert_eq!(convo.confirm(&dialogs, &flags), Advance::Node(2));
+        assert_eq!(convo.confirm(&dialogs, &flags), Advance::End);
+
+        let mut convo = Conversation::new(0);
+        convo.confirm(&dialogs, &flags);
+        convo.move_cursor(&dialogs, &flags, 1);
+        assert_eq!(convo.confirm(&dialogs, &flags), Advance::End);
+
+        // the conditional choice only shows up once "met" is set
+        flags.insert("met".to_string());
+        let mut convo = Conversation::new(0);
+        convo.confirm(&dialogs, &flags);
+        assert_eq!(convo.choices(&dialogs, &flags).len(), 3);
+        convo.move_cursor(&dialogs, &flags, -1);
+        assert_eq!(convo.confirm(&dialogs, &flags), Advance::Node(2));
+        flags.insert("angry".to_string());
+        let mut convo = Conversation::new(0);
+        convo.confirm(&dialogs, &flags);
+        assert_eq!(convo.choices(&dialogs, &flags).len(), 2);
+    }
+}
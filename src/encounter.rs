@@ -0,0 +1,96 @@
+use crate::geom::{Rect, Vec2};
+use rand::Rng;
+
+/// Part of a level where walking around can start a battle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncounterZone {
+    pub area: Rect,
+    // on average, one encounter every this many steps
+    pub rate: u32,
+    // enemy groups and how likely each one is relative to the others
+    pub groups: Vec<(String, u32)>,
+}
+
+impl EncounterZone {
+    /// Rolls for an encounter after one step in this zone, returning the enemy group to fight
+    pub fn roll(&self, rng: &mut impl Rng) -> Option<&str> {
+        let total: u32 = self.groups.iter().map(|(_, weight)| weight).sum();
+        if total == 0 || !rng.gen_ratio(1, self.rate.max(1)) {
+            return None;
+        }
+        let mut pick = rng.gen_range(0..total);
+        for (group, weight) in self.groups.iter() {
+            if pick < *weight {
+                return Some(group);
+            }
+            pick -= weight;
+        }
+        None
+    }
+}
+
+/// Rolls for an encounter after stepping onto `pos`, using the first zone there (if any)
+pub fn roll_encounter<'z>(
+    zones: &'z [EncounterZone],
+    pos: Vec2,
+    rng: &mut impl Rng,
+) -> Option<&'z str> {
+    zones
+        .iter()
+        .find(|zone| zone.area.contains(pos))
+        .and_then(|zone| zone.roll(rng))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, SeedableRng};
+    fn zone() -> EncounterZone {
+        EncounterZone {
+            area: Rect {
+                x: 2,
+                y: 2,
+                w: 3,
+                h: 2,
+            },
+            rate: 4,
+            groups: vec![("slimes".to_string(), 3), ("wolves".to_string(), 1)],
+        }
+    }
+    #[test]
+    fn test_reproducible() {
+        let zones = [zone()];
+        let walk = |seed| {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            (0..200)
+                .map(|_| roll_encounter(&zones, Vec2 { x: 3, y: 3 }, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(walk(7), walk(7));
+        let steps = walk(7);
+        let encounters = steps.iter().flatten().count();
+        // one in four, give or take
+        assert!((30..=70).contains(&encounters), "{encounters} encounters");
+        let slimes = steps.iter().flatten().filter(|g| **g == "slimes").count();
+        assert!(
+            slimes > encounters / 2,
+            "{slimes} of {encounters} were slimes"
+        );
+    }
+    #[test]
+    fn test_outside_zone() {
+        let zones = [zone()];
+        let mut rng = SmallRng::seed_from_u64(1);
+        for pos in [
+            Vec2 { x: 1, y: 2 },
+            Vec2 { x: 5, y: 3 },
+            Vec2 { x: 4, y: 4 },
+        ] {
+            for _ in 0..50 {
+                assert_eq!(roll_encounter(&zones, pos, &mut rng), None);
+            }
+        }
+        let always = EncounterZone { rate: 1, ..zone() };
+        assert!(always.roll(&mut rng).is_some());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::battle::{skill_named, Combatant, Side, Stats};
+use crate::parse::{FieldError, Fields};
+use crate::TILE_SZ;
+use frenderer::sprites::SheetRegion;
+use std::collections::HashMap;
+
+/*
+Enemies and the groups they show up in are read from an ad hoc format like this:
+
+enemy ID SHEET-X SHEET-Y HP MP ATTACK DEFENSE SPEED NAME
+skills SKILL SKILL
+xp AMOUNT
+group ID ENEMY-ID ENEMY-ID ENEMY-ID
+
+SHEET-X and SHEET-Y are the top left of the enemy's 16x16 sprite on the tilesheet.
+A skills line gives the skills (by name) of the enemy defined just before it, and an xp
+line how much XP each party member gets for beating it (none if there's no xp line).
+A group is a list of enemies fought together, and can name the same enemy more than once;
+groups are what dialog `battle` lines and the map refer to.
+Blank lines and lines starting with # are ignored.
+*/
+
+#[derive(Debug, Clone)]
+pub struct EnemyDef {
+    pub name: String,
+    pub stats: Stats,
+    pub skills: Vec<usize>,
+    pub xp: u32,
+    pub sprite: SheetRegion,
+}
+
+impl EnemyDef {
+    pub fn combatant(&self) -> Combatant {
+        Combatant::new(
+            &self.name,
+            Side::Enemy,
+            self.stats,
+            self.skills.clone(),
+            self.sprite,
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Enemies {
+    enemies: HashMap<String, EnemyDef>,
+    groups: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnemiesParseError {
+    Field(FieldError),
+    UnknownKeyword {
+        line: usize,
+        col: usize,
+        keyword: String,
+    },
+    OutsideEnemy {
+        line: usize,
+        col: usize,
+    },
+    DuplicateId {
+        line: usize,
+        col: usize,
+        id: String,
+    },
+    UnknownSkill {
+        line: usize,
+        col: usize,
+        skill: String,
+    },
+    UnknownEnemy {
+        line: usize,
+        col: usize,
+        id: String,
+    },
+    EmptyGroup {
+        line: usize,
+        col: usize,
+        id: String,
+    },
+}
+
+impl EnemiesParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Field(err) => err.line(),
+            Self::UnknownKeyword { line, .. }
+            | Self::OutsideEnemy { line, .. }
+            | Self::DuplicateId { line, .. }
+            | Self::UnknownSkill { line, .. }
+            | Self::UnknownEnemy { line, .. }
+            | Self::EmptyGroup { line, .. } => *line,
+        }
+    }
+    pub fn col(&self) -> usize {
+        match self {
+            Self::Field(err) => err.col(),
+            Self::UnknownKeyword { col, .. }
+            | Self::OutsideEnemy { col, .. }
+            | Self::DuplicateId { col, .. }
+            | Self::UnknownSkill { col, .. }
+            | Self::UnknownEnemy { col, .. }
+            | Self::EmptyGroup { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::Field(err) => err.message(),
+            Self::UnknownKeyword { keyword, .. } => {
+                format!("expected enemy, skills, xp or group, not {keyword:?}")
+            }
+            Self::OutsideEnemy { .. } => {
+                "skills and xp need to come after an enemy line".to_string()
+            }
+            Self::DuplicateId { id, .. } => format!("{id} is already defined"),
+            Self::UnknownSkill { skill, .. } => format!("there's no skill called {skill:?}"),
+            Self::UnknownEnemy { id, .. } => format!("there's no enemy {id}"),
+            Self::EmptyGroup { id, .. } => format!("group {id} has no enemies in it"),
+        }
+    }
+}
+
+impl std::fmt::Display for EnemiesParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for EnemiesParseError {}
+
+impl From<FieldError> for EnemiesParseError {
+    fn from(err: FieldError) -> Self {
+        Self::Field(err)
+    }
+}
+
+impl Enemies {
+    pub fn parse(s: &str) -> Result<Self, EnemiesParseError> {
+        let mut enemies = HashMap::new();
+        let mut groups = HashMap::new();
+        // enemies named by groups, checked once everything's been defined
+        let mut members = vec![];
+        let mut last_enemy: Option<String> = None;
+        for (line_no, line) in s.lines().enumerate() {
+            let line_no = line_no + 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut fields = Fields::new(line_no, line);
+            let (col, keyword) = fields.chunk("enemy, skills, xp or group")?;
+            match keyword {
+                "enemy" => {
+                    let (id_col, id) = fields.chunk("enemy id")?;
+                    let x = fields.num::<u16>("sheet x")?;
+                    let y = fields.num::<u16>("sheet y")?;
+                    let stats = Stats {
+                        max_hp: fields.num("HP")?,
+                        max_mp: fields.num("MP")?,
+                        attack: fields.num("attack")?,
+                        defense: fields.num("defense")?,
+                        speed: fields.num("speed")?,
+                    };
+                    let (_, name) = fields.rest("enemy name")?;
+                    let enemy = EnemyDef {
+                        name: name.to_string(),
+                        stats,
+                        skills: vec![],
+                        xp: 0,
+                        sprite: SheetRegion::new(0, x, y, 14, TILE_SZ as i16, TILE_SZ as i16),
+                    };
+                    if enemies.insert(id.to_string(), enemy).is_some() {
+                        return Err(EnemiesParseError::DuplicateId {
+                            line: line_no,
+                            col: id_col,
+                            id: id.to_string(),
+                        });
+                    }
+                    last_enemy = Some(id.to_string());
+                }
+                "skills" => {
+                    let Some(enemy) = last_enemy.as_ref().and_then(|id| enemies.get_mut(id)) else {
+                        return Err(EnemiesParseError::OutsideEnemy { line: line_no, col });
+                    };
+                    for (col, skill) in &mut fields {
+                        let Some(idx) = skill_named(skill) else {
+                            return Err(EnemiesParseError::UnknownSkill {
+                                line: line_no,
+                                col,
+                                skill: skill.to_string(),
+                            });
+                        };
+                        enemy.skills.push(idx);
+                    }
+                }
+                "xp" => {
+                    let Some(enemy) = last_enemy.as_ref().and_then(|id| enemies.get_mut(id)) else {
+                        return Err(EnemiesParseError::OutsideEnemy { line: line_no, col });
+                    };
+                    enemy.xp = fields.num("xp amount")?;
+                }
+                "group" => {
+                    let (id_col, id) = fields.chunk("group id")?;
+                    let mut group = vec![];
+                    for (col, enemy) in &mut fields {
+                        members.push((line_no, col, enemy.to_string()));
+                        group.push(enemy.to_string());
+                    }
+                    if group.is_empty() {
+                        return Err(EnemiesParseError::EmptyGroup {
+                            line: line_no,
+                            col: id_col,
+                            id: id.to_string(),
+                        });
+                    }
+                    if groups.insert(id.to_string(), group).is_some() {
+                        return Err(EnemiesParseError::DuplicateId {
+                            line: line_no,
+                            col: id_col,
+                            id: id.to_string(),
+                        });
+                    }
+                }
+                _ => {
+                    return Err(EnemiesParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    })
+                }
+            }
+            fields.finish()?;
+        }
+        for (line, col, id) in members {
+            if !enemies.contains_key(&id) {
+                return Err(EnemiesParseError::UnknownEnemy { line, col, id });
+            }
+        }
+        Ok(Self { enemies, groups })
+    }
+    pub fn get(&self, id: &str) -> Option<&EnemyDef> {
+        self.enemies.get(id)
+    }
+    pub fn has_group(&self, group: &str) -> bool {
+        self.groups.contains_key(group)
+    }
+    /// What the group looks like on the map, which is its first enemy's sprite
+    pub fn group_sprite(&self, group: &str) -> Option<SheetRegion> {
+        let first = self.groups.get(group)?.first()?;
+        Some(self.enemies[first].sprite)
+    }
+    /// How much XP beating the whole group is worth
+    pub fn group_xp(&self, group: &str) -> u32 {
+        self.groups
+            .get(group)
+            .map_or(0, |ids| ids.iter().map(|id| self.enemies[id].xp).sum())
+    }
+    /// Fresh combatants for everyone in a group, or None if there's no such group
+    pub fn group(&self, group: &str) -> Option<Vec<Combatant>> {
+        self.groups
+            .get(group)
+            .map(|ids| ids.iter().map(|id| self.enemies[id].combatant()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const SAMPLE: &str = "# a comment
+enemy slime 0 646 12 0 5 1 3 Green Slime
+enemy imp 17 646 10 8 4 1 6 Imp
+skills fire
+xp 7
+
+group slimes slime slime
+group mixed imp slime
+";
+    #[test]
+    fn test_parse_enemies() {
+        let enemies = Enemies::parse(SAMPLE).unwrap();
+        let imp = enemies.get("imp").unwrap();
+        assert_eq!(imp.name, "Imp");
+        assert_eq!(
+            imp.stats,
+            Stats {
+                max_hp: 10,
+                max_mp: 8,
+                attack: 4,
+                defense: 1,
+                speed: 6
+            }
+        );
+        assert_eq!(imp.skills, vec![skill_named("fire").unwrap()]);
+        assert_eq!(imp.xp, 7);
+        assert_eq!(enemies.group_xp("mixed"), 7);
+        assert_eq!(enemies.group_xp("slimes"), 0);
+        let slimes = enemies.group("slimes").unwrap();
+        assert_eq!(slimes.len(), 2);
+        assert_eq!(slimes[0].name, "Green Slime");
+        assert_eq!(slimes[0].hp, 12);
+        assert!(enemies.has_group("mixed"));
+        let sprite = enemies.group_sprite("mixed").unwrap();
+        assert_eq!((sprite.x, sprite.y), (17, 646));
+        assert!(enemies.group("wolves").is_none());
+    }
+    #[test]
+    fn test_parse_errors() {
+        let err = |src: &str| Enemies::parse(src).unwrap_err();
+        assert_eq!(
+            err(&SAMPLE.replace("skills fire", "skills fire ice")),
+            EnemiesParseError::UnknownSkill {
+                line: 4,
+                col: 13,
+                skill: "ice".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("mixed imp", "mixed bat")),
+            EnemiesParseError::UnknownEnemy {
+                line: 8,
+                col: 13,
+                id: "bat".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("enemy imp", "enemy slime")),
+            EnemiesParseError::DuplicateId {
+                line: 3,
+                col: 7,
+                id: "slime".to_string()
+            }
+        );
+        assert_eq!(
+            err("skills fire\n"),
+            EnemiesParseError::OutsideEnemy { line: 1, col: 1 }
+        );
+        assert_eq!(
+            err("xp 3\n"),
+            EnemiesParseError::OutsideEnemy { line: 1, col: 1 }
+        );
+        assert_eq!(
+            err("group empty\n"),
+            EnemiesParseError::EmptyGroup {
+                line: 1,
+                col: 7,
+                id: "empty".to_string()
+            }
+        );
+        assert_eq!(err("enemy slime 0 646 lots 0 5 1 3 Slime\n").line(), 1);
+    }
+}
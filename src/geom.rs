@@ -10,3 +10,9 @@ pub struct Rect {
     pub w: u16,
     pub h: u16,
 }
+
+impl Rect {
+    pub fn contains(&self, pos: Vec2) -> bool {
+        (self.x..self.x + self.w).contains(&pos.x) && (self.y..self.y + self.h).contains(&pos.y)
+    }
+}
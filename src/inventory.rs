@@ -0,0 +1,70 @@
+/// The party's items, as stacks of item ids with counts, in the order they were picked up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inventory {
+    stacks: Vec<(String, u32)>,
+}
+
+impl Inventory {
+    pub fn add(&mut self, item: &str, count: u32) {
+        if count == 0 {
+            return;
+        }
+        match self.stacks.iter_mut().find(|(id, _)| id == item) {
+            Some((_, have)) => *have += count,
+            None => self.stacks.push((item.to_string(), count)),
+        }
+    }
+    /// Takes away up to `count` of an item, returning how many were actually removed.
+    /// Empty stacks are dropped.
+    pub fn remove(&mut self, item: &str, count: u32) -> u32 {
+        let Some(idx) = self.stacks.iter().position(|(id, _)| id == item) else {
+            return 0;
+        };
+        let have = &mut self.stacks[idx].1;
+        let removed = count.min(*have);
+        *have -= removed;
+        if *have == 0 {
+            self.stacks.remove(idx);
+        }
+        removed
+    }
+    pub fn count(&self, item: &str) -> u32 {
+        self.stacks
+            .iter()
+            .find(|(id, _)| id == item)
+            .map(|(_, n)| *n)
+            .unwrap_or(0)
+    }
+    pub fn stacks(&self) -> &[(String, u32)] {
+        &self.stacks
+    }
+    pub fn len(&self) -> usize {
+        self.stacks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.stacks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_stacks() {
+        let mut inv = Inventory::default();
+        inv.add("potion", 2);
+        inv.add("key", 1);
+        inv.add("potion", 1);
+        inv.add("nothing", 0);
+        assert_eq!(
+            inv.stacks(),
+            &[("potion".to_string(), 3), ("key".to_string(), 1)]
+        );
+        assert_eq!(inv.remove("potion", 2), 2);
+        assert_eq!(inv.count("potion"), 1);
+        assert_eq!(inv.remove("potion", 5), 1);
+        assert_eq!(inv.count("potion"), 0);
+        assert_eq!(inv.remove("ether", 1), 0);
+        assert_eq!(inv.len(), 1);
+    }
+}
@@ -0,0 +1,382 @@
+use crate::battle::Stats;
+use crate::parse::{FieldError, Fields};
+use std::collections::HashMap;
+
+/*
+Items are read from an ad hoc format like this:
+
+item ID KIND NAME
+desc TEXT
+effect heal AMOUNT
+effect mp AMOUNT
+bonus STAT AMOUNT
+
+KIND is consumable (used up when used), weapon, armor or accessory (equipment, worn in
+the slot of the same name), or key (story items, which can't be used up or dropped).
+The lines after an item line describe that item: effects are what a consumable does to
+whoever it's used on, and bonuses are what a piece of equipment adds to its wearer's hp,
+mp, attack, defense or speed.  Blank lines and lines starting with # are ignored.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Weapon,
+    Armor,
+    Accessory,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 3] = [Slot::Weapon, Slot::Armor, Slot::Accessory];
+    pub fn name(self) -> &'static str {
+        match self {
+            Slot::Weapon => "Weapon",
+            Slot::Armor => "Armor",
+            Slot::Accessory => "Accessory",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Consumable,
+    Equipment(Slot),
+    Key,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemEffect {
+    Heal(u32),
+    RestoreMp(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemDef {
+    pub name: String,
+    pub desc: String,
+    pub kind: ItemKind,
+    pub effects: Vec<ItemEffect>,
+    // what the item adds to its wearer's stats, if it's equipment
+    pub bonus: Stats,
+}
+
+impl ItemDef {
+    /// Whether the item does anything when it's used on someone
+    pub fn usable(&self) -> bool {
+        self.kind == ItemKind::Consumable && !self.effects.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Items {
+    items: HashMap<String, ItemDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemsParseError {
+    Field(FieldError),
+    UnknownKeyword {
+        line: usize,
+        col: usize,
+        keyword: String,
+    },
+    OutsideItem {
+        line: usize,
+        col: usize,
+    },
+    DuplicateId {
+        line: usize,
+        col: usize,
+        id: String,
+    },
+    BadKind {
+        line: usize,
+        col: usize,
+        kind: String,
+    },
+    UnknownEffect {
+        line: usize,
+        col: usize,
+        effect: String,
+    },
+    UnknownStat {
+        line: usize,
+        col: usize,
+        stat: String,
+    },
+}
+
+impl ItemsParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Field(err) => err.line(),
+            Self::UnknownKeyword { line, .. }
+            | Self::OutsideItem { line, .. }
+            | Self::DuplicateId { line, .. }
+            | Self::BadKind { line, .. }
+            | Self::UnknownEffect { line, .. }
+            | Self::UnknownStat { line, .. } => *line,
+        }
+    }
+    pub fn col(&self) -> usize {
+        match self {
+            Self::Field(err) => err.col(),
+            Self::UnknownKeyword { col, .. }
+            | Self::OutsideItem { col, .. }
+            | Self::DuplicateId { col, .. }
+            | Self::BadKind { col, .. }
+            | Self::UnknownEffect { col, .. }
+            | Self::UnknownStat { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::Field(err) => err.message(),
+            Self::UnknownKeyword { keyword, .. } => {
+                format!("expected item, desc, effect or bonus, not {keyword:?}")
+            }
+            Self::OutsideItem { .. } => "this needs to come after an item line".to_string(),
+            Self::DuplicateId { id, .. } => format!("item {id} is already defined"),
+            Self::BadKind { kind, .. } => {
+                format!("item kind {kind:?} should be consumable, weapon, armor, accessory or key")
+            }
+            Self::UnknownEffect { effect, .. } => {
+                format!("expected heal or mp, not {effect:?}")
+            }
+            Self::UnknownStat { stat, .. } => {
+                format!("expected hp, mp, attack, defense or speed, not {stat:?}")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ItemsParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for ItemsParseError {}
+
+impl From<FieldError> for ItemsParseError {
+    fn from(err: FieldError) -> Self {
+        Self::Field(err)
+    }
+}
+
+impl Items {
+    pub fn parse(s: &str) -> Result<Self, ItemsParseError> {
+        let mut items = HashMap::new();
+        let mut last_item: Option<String> = None;
+        for (line_no, line) in s.lines().enumerate() {
+            let line_no = line_no + 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut fields = Fields::new(line_no, line);
+            let (col, keyword) = fields.chunk("item, desc, effect or bonus")?;
+            if keyword == "item" {
+                let (id_col, id) = fields.chunk("item id")?;
+                let (kind_col, kind) = fields.chunk("item kind")?;
+                let kind = match kind {
+                    "consumable" => ItemKind::Consumable,
+                    "weapon" => ItemKind::Equipment(Slot::Weapon),
+                    "armor" => ItemKind::Equipment(Slot::Armor),
+                    "accessory" => ItemKind::Equipment(Slot::Accessory),
+                    "key" => ItemKind::Key,
+                    _ => {
+                        return Err(ItemsParseError::BadKind {
+                            line: line_no,
+                            col: kind_col,
+                            kind: kind.to_string(),
+                        })
+                    }
+                };
+                let item = ItemDef {
+                    name: fields.rest("item name")?.1.to_string(),
+                    desc: String::new(),
+                    kind,
+                    effects: vec![],
+                    bonus: Stats::default(),
+                };
+                if items.insert(id.to_string(), item).is_some() {
+                    return Err(ItemsParseError::DuplicateId {
+                        line: line_no,
+                        col: id_col,
+                        id: id.to_string(),
+                    });
+                }
+                last_item = Some(id.to_string());
+                continue;
+            }
+            let Some(item) = last_item.as_ref().and_then(|id| items.get_mut(id)) else {
+                return Err(match keyword {
+                    "desc" | "effect" | "bonus" => {
+                        ItemsParseError::OutsideItem { line: line_no, col }
+                    }
+                    _ => ItemsParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    },
+                });
+            };
+            match keyword {
+                "desc" => item.desc = fields.rest("item description")?.1.to_string(),
+                "effect" => {
+                    let (col, effect) = fields.chunk("effect type")?;
+                    let effect = match effect {
+                        "heal" => ItemEffect::Heal(fields.num("amount")?),
+                        "mp" => ItemEffect::RestoreMp(fields.num("amount")?),
+                        _ => {
+                            return Err(ItemsParseError::UnknownEffect {
+                                line: line_no,
+                                col,
+                                effect: effect.to_string(),
+                            })
+                        }
+                    };
+                    item.effects.push(effect);
+                }
+                "bonus" => {
+                    let (col, stat) = fields.chunk("stat")?;
+                    let bonus = &mut item.bonus;
+                    let stat = match stat {
+                        "hp" => &mut bonus.max_hp,
+                        "mp" => &mut bonus.max_mp,
+                        "attack" => &mut bonus.attack,
+                        "defense" => &mut bonus.defense,
+                        "speed" => &mut bonus.speed,
+                        _ => {
+                            return Err(ItemsParseError::UnknownStat {
+                                line: line_no,
+                                col,
+                                stat: stat.to_string(),
+                            })
+                        }
+                    };
+                    *stat += fields.num::<u32>("amount")?;
+                }
+                _ => {
+                    return Err(ItemsParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    })
+                }
+            }
+            fields.finish()?;
+        }
+        Ok(Self { items })
+    }
+    pub fn get(&self, id: &str) -> Option<&ItemDef> {
+        self.items.get(id)
+    }
+    pub fn contains(&self, id: &str) -> bool {
+        self.items.contains_key(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const SAMPLE: &str = "# a comment
+item potion consumable Potion
+desc Restores 20 HP.
+effect heal 20
+
+item elixir consumable Big Elixir
+effect heal 50
+effect mp 20
+item key key Old Key
+desc It opens something.
+item ring accessory Speed Ring
+bonus speed 2
+bonus hp 5
+";
+    #[test]
+    fn test_parse_items() {
+        let items = Items::parse(SAMPLE).unwrap();
+        assert_eq!(
+            items.get("potion"),
+            Some(&ItemDef {
+                name: "Potion".to_string(),
+                desc: "Restores 20 HP.".to_string(),
+                kind: ItemKind::Consumable,
+                effects: vec![ItemEffect::Heal(20)],
+                bonus: Stats::default(),
+            })
+        );
+        let elixir = items.get("elixir").unwrap();
+        assert_eq!(elixir.name, "Big Elixir");
+        assert_eq!(
+            elixir.effects,
+            vec![ItemEffect::Heal(50), ItemEffect::RestoreMp(20)]
+        );
+        assert!(elixir.usable());
+        assert!(!items.get("key").unwrap().usable());
+        assert!(!items.contains("sword"));
+        let ring = items.get("ring").unwrap();
+        assert_eq!(ring.kind, ItemKind::Equipment(Slot::Accessory));
+        assert_eq!(
+            ring.bonus,
+            Stats {
+                max_hp: 5,
+                speed: 2,
+                ..Stats::default()
+            }
+        );
+        assert!(!ring.usable());
+    }
+    #[test]
+    fn test_parse_errors() {
+        let err = |src: &str| Items::parse(src).unwrap_err();
+        assert_eq!(
+            err(&SAMPLE.replace("key key", "key thing")),
+            ItemsParseError::BadKind {
+                line: 9,
+                col: 10,
+                kind: "thing".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("effect mp", "effect fly")),
+            ItemsParseError::UnknownEffect {
+                line: 8,
+                col: 8,
+                effect: "fly".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("item elixir", "item potion")),
+            ItemsParseError::DuplicateId {
+                line: 6,
+                col: 6,
+                id: "potion".to_string()
+            }
+        );
+        assert_eq!(
+            err("desc Nothing\n"),
+            ItemsParseError::OutsideItem { line: 1, col: 1 }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("desc It", "describe It")),
+            ItemsParseError::UnknownKeyword {
+                line: 10,
+                col: 1,
+                keyword: "describe".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("bonus hp", "bonus luck")),
+            ItemsParseError::UnknownStat {
+                line: 13,
+                col: 7,
+                stat: "luck".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("heal 20", "heal 20 extra")),
+            ItemsParseError::Field(FieldError::TrailingContent { line: 4, col: 16 })
+        );
+    }
+}
@@ -1,7 +1,11 @@
+use crate::encounter::EncounterZone;
 use crate::geom::*;
 use crate::grid::Grid;
+use crate::parse::{FieldError, Fields};
 use crate::EntityType;
+use crate::TileAnim;
 use crate::TileData;
+use crate::TileFlags;
 use crate::Tileset;
 use crate::TILE_SZ;
 use frenderer::{
@@ -11,23 +15,329 @@ use frenderer::{
 use std::collections::HashMap;
 use std::str::FromStr;
 
+/// One map section of a level: a grid of tiles drawn at the same depth, with `None` where
+/// the layer has nothing and whatever's under it shows through
+pub struct MapLayer {
+    name: String,
+    depth: u16,
+    grid: Grid<Option<u8>>,
+}
+
+impl MapLayer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+}
+
+// the layer a level gets if its first map section doesn't say otherwise
+const GROUND: &str = "ground";
+const GROUND_DEPTH: u16 = 16;
+// a map cell with no tile in it
+const EMPTY: &str = "_";
+
 #[allow(dead_code)]
 pub struct Level {
     name: String,
-    grid: Grid<u8>,
+    // bottom to top, starting with the ground
+    layers: Vec<MapLayer>,
     tileset: Tileset,
     starts: Vec<(EntityType, Vec2)>,
+    // which line of the level file each start came from, for error reporting
+    start_lines: Vec<usize>,
+    encounters: Vec<EncounterZone>,
+    encounter_lines: Vec<usize>,
+    // the line just past the end of the file, where the starts section ends
+    starts_end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LevelParseError {
+    UnknownSymbol {
+        line: usize,
+        col: usize,
+        sym: String,
+    },
+    DuplicateSymbol {
+        line: usize,
+        col: usize,
+        sym: String,
+    },
+    ReservedSymbol {
+        line: usize,
+        col: usize,
+        sym: String,
+    },
+    DuplicateLayer {
+        line: usize,
+        col: usize,
+        layer: String,
+    },
+    BadFlag {
+        line: usize,
+        col: usize,
+        flag: String,
+    },
+    BadNumber {
+        line: usize,
+        col: usize,
+        field: &'static str,
+        text: String,
+    },
+    MissingField {
+        line: usize,
+        col: usize,
+        field: &'static str,
+    },
+    UnknownEntity {
+        line: usize,
+        col: usize,
+        etype: String,
+    },
+    WrongRowWidth {
+        line: usize,
+        col: usize,
+        expected: usize,
+        found: usize,
+    },
+    WrongRowCount {
+        line: usize,
+        col: usize,
+        expected: usize,
+        found: usize,
+    },
+    MissingSection {
+        line: usize,
+        col: usize,
+        section: &'static str,
+    },
+    TrailingContent {
+        line: usize,
+        col: usize,
+    },
+}
+
+impl LevelParseError {
+    /// 1-based line of the level file where the problem is
+    pub fn line(&self) -> usize {
+        match self {
+            Self::UnknownSymbol { line, .. }
+            | Self::DuplicateSymbol { line, .. }
+            | Self::ReservedSymbol { line, .. }
+            | Self::DuplicateLayer { line, .. }
+            | Self::BadFlag { line, .. }
+            | Self::BadNumber { line, .. }
+            | Self::MissingField { line, .. }
+            | Self::UnknownEntity { line, .. }
+            | Self::WrongRowWidth { line, .. }
+            | Self::WrongRowCount { line, .. }
+            | Self::MissingSection { line, .. }
+            | Self::TrailingContent { line, .. } => *line,
+        }
+    }
+    /// 1-based column (in characters) within [`LevelParseError::line`]
+    pub fn col(&self) -> usize {
+        match self {
+            Self::UnknownSymbol { col, .. }
+            | Self::DuplicateSymbol { col, .. }
+            | Self::ReservedSymbol { col, .. }
+            | Self::DuplicateLayer { col, .. }
+            | Self::BadFlag { col, .. }
+            | Self::BadNumber { col, .. }
+            | Self::MissingField { col, .. }
+            | Self::UnknownEntity { col, .. }
+            | Self::WrongRowWidth { col, .. }
+            | Self::WrongRowCount { col, .. }
+            | Self::MissingSection { col, .. }
+            | Self::TrailingContent { col, .. } => *col,
+        }
+    }
+    /// What went wrong, without the line and column
+    pub fn message(&self) -> String {
+        match self {
+            Self::UnknownSymbol { sym, .. } => {
+                format!("tile symbol {sym:?} is not in the legend")
+            }
+            Self::DuplicateSymbol { sym, .. } => {
+                format!("symbol {sym:?} is already in the legend")
+            }
+            Self::ReservedSymbol { sym, .. } => {
+                format!(
+                    "symbol {sym:?} can't be a tile (it's used for empty cells and layer names)"
+                )
+            }
+            Self::DuplicateLayer { layer, .. } => {
+                format!("there's already a map layer called {layer:?}")
+            }
+            Self::BadFlag { flag, .. } => {
+                format!("tile flags {flag:?} should be letters from {FLAG_LETTERS:?} or flag words, joined with +")
+            }
+            Self::BadNumber { field, text, .. } => {
+                format!("couldn't parse {field} from {text:?}")
+            }
+            Self::MissingField { field, .. } => format!("expected {field}"),
+            Self::UnknownEntity { etype, .. } => format!("unrecognized entity type {etype:?}"),
+            Self::WrongRowWidth {
+                expected, found, ..
+            } => format!("map row has {found} tiles but the level is {expected} wide"),
+            Self::WrongRowCount {
+                expected, found, ..
+            } => format!("map has {found} rows but the level is {expected} tall"),
+            Self::MissingSection { section, .. } => format!("missing {section} section"),
+            Self::TrailingContent { .. } => "unexpected trailing content".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for LevelParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for LevelParseError {}
+
+impl From<FieldError> for LevelParseError {
+    fn from(err: FieldError) -> Self {
+        match err {
+            FieldError::MissingField { line, col, field } => {
+                Self::MissingField { line, col, field }
+            }
+            FieldError::BadNumber {
+                line,
+                col,
+                field,
+                text,
+            } => Self::BadNumber {
+                line,
+                col,
+                field,
+                text,
+            },
+            FieldError::TrailingContent { line, col } => Self::TrailingContent { line, col },
+        }
+    }
+}
+
+// The one-letter tile flags, in the order TileFlags lists them
+const FLAG_LETTERS: &str = "oswcmn";
+
+// Reads a legend line's tile flags (see Level::parse), or None if they don't make sense
+fn parse_flags(text: &str) -> Option<TileFlags> {
+    let mut flags = TileFlags::default();
+    for part in text.split('+') {
+        let word = match part.split_once(':') {
+            Some(("damage", amount)) => {
+                flags.damage += amount.parse::<u32>().ok()?;
+                continue;
+            }
+            Some(("ledge", dir)) => {
+                flags.ledge = Some(match dir {
+                    "up" => (0, -1),
+                    "down" => (0, 1),
+                    "left" => (-1, 0),
+                    "right" => (1, 0),
+                    _ => return None,
+                });
+                continue;
+            }
+            Some(_) => return None,
+            None => part,
+        };
+        let letters = match word {
+            "open" => "o",
+            "solid" => "s",
+            "water" => "w",
+            "counter" => "c",
+            "slow" => "m",
+            "noenc" | "noencounter" => "n",
+            "" => return None,
+            letters => letters,
+        };
+        for letter in letters.chars() {
+            match letter {
+                'o' => {}
+                's' => flags.solid = true,
+                'w' => flags.water = true,
+                'c' => flags.counter = true,
+                'm' => flags.slow = true,
+                'n' => flags.no_encounter = true,
+                _ => return None,
+            }
+        }
+    }
+    Some(flags)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Metadata,
+    Legend,
+    Map,
+    Starts,
+    Done,
+}
+impl State {
+    fn next(self) -> Self {
+        match self {
+            Self::Metadata => Self::Legend,
+            Self::Legend => Self::Map,
+            Self::Map => Self::Starts,
+            Self::Starts => Self::Done,
+            Self::Done => Self::Done,
+        }
+    }
+    // Leaving this section at `line`; make sure it and everything before it actually got filled in.
+    fn check_complete(
+        self,
+        line: usize,
+        dims: Option<(u16, u16)>,
+        has_legend: bool,
+        rows: usize,
+    ) -> Result<(), LevelParseError> {
+        let missing = |section| LevelParseError::MissingSection {
+            line,
+            col: 1,
+            section,
+        };
+        let Some((_w, h)) = dims else {
+            return Err(missing("metadata"));
+        };
+        if self == State::Metadata {
+            return Ok(());
+        }
+        if !has_legend {
+            return Err(missing("legend"));
+        }
+        if self == State::Legend {
+            return Ok(());
+        }
+        if rows == 0 {
+            return Err(missing("map"));
+        }
+        if rows < h as usize {
+            return Err(LevelParseError::WrongRowCount {
+                line,
+                col: 1,
+                expected: h as usize,
+                found: rows,
+            });
+        }
+        Ok(())
+    }
 }
 
 impl Level {
     /*
-    We'll read from an ad hoc format like this, where FLAGS is either S (solid) or O (open) but could be other stuff later:
+    We'll read from an ad hoc format like this, where FLAGS says what the tile does (see below):
 
     LEVELNAME W H
     ====
     SYM FLAGS X Y W H
     SYM FLAGS X Y W H
-    SYM FLAGS X Y W H
+    SYM FLAGS X Y W H frames SECS X Y X Y
     ====
     SYM SYM SYM SYM SYM
     SYM SYM SYM SYM SYM
@@ -35,195 +345,911 @@ impl Level {
     SYM SYM SYM SYM SYM
     SYM SYM SYM SYM SYM
     ====
+    layer NAME DEPTH
+    SYM SYM SYM _   _
+    _   _   SYM _   _
+    _   _   _   _   _
+    _   _   _   _   _
+    _   _   _   _   _
+    ====
     player X Y
     npc DIALOG_ID x y
     npc DIALOG_ID x y
     npc DIALOG_ID x y
     door LEVELNAME TO-X TO-Y X Y
+    enemy GROUP X Y
+    chest ITEM_ID COUNT X Y
+    encounter X Y W H RATE GROUP WEIGHT GROUP WEIGHT
     you can add more types of thing if you want
+
+    There can be any number of map sections, each one a layer drawn on top of the ones
+    before it.  A map section can start with a layer line giving it a NAME and a sprite
+    DEPTH (lower is further in front; people and things on the map are at 14 and 15, so
+    a roof the player walks behind might be at 13); the first one is "ground" at 16 if it
+    doesn't say.  _ is an empty cell that lets the layers below show through, so neither
+    it nor "layer" can be a tile symbol.  A spot has the flags of every layer's tile there.
+
+    A tile's FLAGS are one or more of these, joined with + (like o+noenc or s+counter),
+    where the one-letter ones can also be run together (like sw):
+      o, open           nothing special
+      s, solid          can't be walked onto
+      w, water          can only be crossed carrying the right item
+      c, counter        can't be walked onto, but you can talk to whoever's across it
+      m, slow           the next step takes longer
+      n, noenc          no random battles here (noencounter works too)
+      damage:N          everyone in the party loses N HP stepping onto it
+      ledge:DIR         a one-way drop that can only be walked onto going DIR (up, down,
+                        left or right), and not back off the way you came
+
+    A legend line can end with frames, to make an animated tile: it starts on the X Y
+    before it and moves on to each of the X Ys after it (all W by H) every SECS seconds,
+    then starts over.
+
+    An encounter line isn't a thing on the map, but a rectangle where each step has a
+    one-in-RATE chance of starting a battle with one of the enemy GROUPs, picked in
+    proportion to their WEIGHTs.  Where zones overlap, the first one listed wins.
+
+    Rather than panicking on a malformed file, parse reports the first problem as a
+    LevelParseError carrying the 1-based line and column where it went wrong.
     */
-    pub fn from_str(s: &str) -> Self {
-        enum State {
-            Metadata,
-            Legend,
-            Map,
-            Starts,
-            Done,
-        }
-        impl State {
-            fn next(self) -> Self {
-                match self {
-                    Self::Metadata => Self::Legend,
-                    Self::Legend => Self::Map,
-                    Self::Map => Self::Starts,
-                    Self::Starts => Self::Done,
-                    Self::Done => Self::Done,
-                }
-            }
-        }
+    pub fn parse(s: &str) -> Result<Self, LevelParseError> {
         let mut state = State::Metadata;
         let mut name = None;
         let mut dims = None;
         let mut legend: HashMap<String, (u8, TileData)> = std::collections::HashMap::new();
-        let mut grid = vec![];
+        // the frames of every animated tile in the legend
+        let mut frames = vec![];
+        // name, depth, and cells of each map section so far
+        let mut layers: Vec<(String, u16, Vec<Option<u8>>)> = vec![];
+        let mut rows = 0;
+        // whether we're on the first line of a section, where a layer line can go
+        let mut section_start = false;
+        let mut line_no = 0;
         let mut starts = vec![];
+        let mut start_lines = vec![];
+        let mut encounters = vec![];
+        let mut encounter_lines = vec![];
         for line in s.lines() {
-            if line.is_empty() {
+            line_no += 1;
+            if line.trim().is_empty() {
                 continue;
             } else if line.chars().all(|c| c == '=') {
+                state.check_complete(line_no, dims, !legend.is_empty(), rows)?;
                 state = state.next();
-            } else {
-                match state {
-                    State::Metadata => {
-                        assert_eq!(name, None, "Two metadata lines! in {line}");
-                        let mut chunks = line.split_whitespace();
-                        name = Some(
-                            chunks
-                                .next()
-                                .expect("No name in metadata line {line}")
-                                .to_string(),
-                        );
-                        dims = Some((
-                            u16::from_str(chunks.next().expect("No width in metadata line {line}"))
-                                .expect("Couldn't parse width as u16 in {line}"),
-                            u16::from_str(
-                                chunks.next().expect("No height in metadata line {line}"),
-                            )
-                            .expect("Couldn't parse height as u16 in {line}"),
-                        ));
+                if state == State::Map {
+                    layers.push((GROUND.to_string(), GROUND_DEPTH, vec![]));
+                }
+                section_start = true;
+                continue;
+            }
+            let mut fields = Fields::new(line_no, line);
+            if std::mem::take(&mut section_start)
+                && matches!(state, State::Map | State::Starts)
+                && fields.peek().is_some_and(|(_, word)| word == "layer")
+            {
+                fields.chunk("layer")?;
+                let (col, layer) = fields.chunk("layer name")?;
+                let depth = fields.num::<u16>("layer depth")?;
+                fields.finish()?;
+                let named = &layers[..layers.len() - usize::from(state == State::Map)];
+                if named.iter().any(|(name, _, _)| name == layer) {
+                    return Err(LevelParseError::DuplicateLayer {
+                        line: line_no,
+                        col,
+                        layer: layer.to_string(),
+                    });
+                }
+                if state == State::Starts {
+                    // another map section, rather than the starts
+                    layers.push((String::new(), 0, vec![]));
+                    rows = 0;
+                    state = State::Map;
+                }
+                let last = layers.last_mut().unwrap();
+                (last.0, last.1) = (layer.to_string(), depth);
+                continue;
+            }
+            match state {
+                State::Metadata => {
+                    if name.is_some() {
+                        return Err(fields.trailing().into());
                     }
-                    State::Legend => {
-                        let mut chunks = line.split_whitespace();
-                        let sym = chunks.next().expect("Couldn't get tile symbol in {line}");
-                        assert!(!legend.contains_key(sym), "Symbol {sym} already in legend");
-                        let flags = chunks
-                            .next()
-                            .expect("Couldn't get tile flags in {line}")
-                            .to_lowercase();
-                        assert!(flags == "o" || flags == "s", "The only valid flags are o(pen) or s(olid), but you could parse other kinds here in {line}");
-                        let x =
-                            u16::from_str(chunks.next().expect("No sheet x in legend line {line}"))
-                                .expect("Couldn't parse sheet x as u16 in {line}");
-                        let y =
-                            u16::from_str(chunks.next().expect("No sheet y in legend line {line}"))
-                                .expect("Couldn't parse sheet y as u16 in {line}");
-                        let w =
-                            i16::from_str(chunks.next().expect("No sheet w in legend line {line}"))
-                                .expect("Couldn't parse sheet w as i16 in {line}");
-                        let h =
-                            i16::from_str(chunks.next().expect("No sheet h in legend line {line}"))
-                                .expect("Couldn't parse sheet h as i16 in {line}");
-                        let data = TileData {
-                            solid: flags == "s",
-                            sheet_region: SheetRegion::new(0, x, y, 16, w, h),
-                        };
-                        legend.insert(sym.to_string(), (legend.len() as u8, data));
+                    name = Some(fields.chunk("level name")?.1.to_string());
+                    dims = Some((fields.num::<u16>("width")?, fields.num::<u16>("height")?));
+                    fields.finish()?;
+                }
+                State::Legend => {
+                    let (col, sym) = fields.chunk("tile symbol")?;
+                    if sym == EMPTY || sym == "layer" {
+                        return Err(LevelParseError::ReservedSymbol {
+                            line: line_no,
+                            col,
+                            sym: sym.to_string(),
+                        });
                     }
-                    State::Map => {
-                        let old_len = grid.len();
-                        grid.extend(line.split_whitespace().map(|sym| legend[sym].0));
-                        assert_eq!(
-                            old_len + dims.unwrap().0 as usize,
-                            grid.len(),
-                            "map line is too short: {line} for map dims {dims:?}"
-                        );
+                    if legend.contains_key(sym) {
+                        return Err(LevelParseError::DuplicateSymbol {
+                            line: line_no,
+                            col,
+                            sym: sym.to_string(),
+                        });
                     }
-                    State::Starts => {
-                        let mut chunks = line.split_whitespace();
-                        let etype = chunks
-                            .next()
-                            .expect("Couldn't get entity start type {line}");
-                        let etype = match etype {
-                            "player" => EntityType::Player,
-                            "npc" => {
-                                let dlg = chunks.next().expect("Couldn't get dialog ID on {line}");
-                                let dlg = usize::from_str(dlg)
-                                    .expect("Dialog ID not a valid integer {dlg} in {line}");
-                                EntityType::Npc(dlg)
-                            }
-                            "door" => {
-                                let to_room = chunks.next().expect("Couldn't get dest room {line}");
-                                let to_x = u16::from_str(
-                                    chunks.next().expect("No dest x coord in door line {line}"),
-                                )
-                                .expect("Couldn't parse x coord as u16 in {line}");
-                                let to_y = u16::from_str(
-                                    chunks.next().expect("No dest y coord in door line {line}"),
-                                )
-                                .expect("Couldn't parse y coord as u16 in {line}");
-                                EntityType::Door(to_room.to_string(), to_x, to_y)
+                    let (col, flags) = fields.chunk("tile flags")?;
+                    let Some(flags) = parse_flags(&flags.to_lowercase()) else {
+                        return Err(LevelParseError::BadFlag {
+                            line: line_no,
+                            col,
+                            flag: flags.to_string(),
+                        });
+                    };
+                    let x = fields.num::<u16>("sheet x")?;
+                    let y = fields.num::<u16>("sheet y")?;
+                    let w = fields.num::<i16>("sheet w")?;
+                    let h = fields.num::<i16>("sheet h")?;
+                    let sheet_region = SheetRegion::new(0, x, y, 16, w, h);
+                    let mut anim = None;
+                    if fields.peek().is_some_and(|(_, word)| word == "frames") {
+                        fields.chunk("frames")?;
+                        let (col, text) = fields.chunk("frame duration")?;
+                        let duration = text
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|secs| *secs > 0.0)
+                            .ok_or_else(|| LevelParseError::BadNumber {
+                                line: line_no,
+                                col,
+                                field: "frame duration",
+                                text: text.to_string(),
+                            })?;
+                        let first = frames.len();
+                        frames.push(sheet_region);
+                        loop {
+                            let x = fields.num::<u16>("frame x")?;
+                            let y = fields.num::<u16>("frame y")?;
+                            frames.push(SheetRegion::new(0, x, y, 16, w, h));
+                            if fields.peek().is_none() {
+                                break;
                             }
-                            _ => panic!("Unrecognized entity type in {line}"),
+                        }
+                        anim = Some(TileAnim {
+                            first,
+                            count: frames.len() - first,
+                            duration,
+                        });
+                    }
+                    fields.finish()?;
+                    let data = TileData {
+                        flags,
+                        sheet_region,
+                        anim,
+                    };
+                    legend.insert(sym.to_string(), (legend.len() as u8, data));
+                }
+                State::Map => {
+                    // the metadata section can't be empty if we got here
+                    let (w, h) = dims.unwrap();
+                    if rows == h as usize {
+                        return Err(LevelParseError::WrongRowCount {
+                            line: line_no,
+                            col: 1,
+                            expected: h as usize,
+                            found: rows + 1,
+                        });
+                    }
+                    let grid = &mut layers.last_mut().unwrap().2;
+                    let old_len = grid.len();
+                    for (col, sym) in fields.by_ref() {
+                        if sym == EMPTY {
+                            grid.push(None);
+                            continue;
+                        }
+                        let Some((idx, _)) = legend.get(sym) else {
+                            return Err(LevelParseError::UnknownSymbol {
+                                line: line_no,
+                                col,
+                                sym: sym.to_string(),
+                            });
                         };
-                        let x =
-                            u16::from_str(chunks.next().expect("No x coord in start line {line}"))
-                                .expect("Couldn't parse x coord as u16 in {line}");
-                        let y =
-                            u16::from_str(chunks.next().expect("No y coord in start line {line}"))
-                                .expect("Couldn't parse y coord as u16 in {line}");
-                        starts.push((etype, Vec2 { x, y }));
+                        grid.push(Some(*idx));
                     }
-                    State::Done => {
-                        panic!("Unexpected file content after parsing finished in {line}")
+                    if grid.len() - old_len != w as usize {
+                        return Err(LevelParseError::WrongRowWidth {
+                            line: line_no,
+                            col: 1,
+                            expected: w as usize,
+                            found: grid.len() - old_len,
+                        });
                     }
+                    rows += 1;
                 }
+                State::Starts => {
+                    let (col, etype) = fields.chunk("entity type")?;
+                    if etype == "encounter" {
+                        let area = Rect {
+                            x: fields.num("x coord")?,
+                            y: fields.num("y coord")?,
+                            w: fields.num("width")?,
+                            h: fields.num("height")?,
+                        };
+                        let rate = fields.num::<u32>("encounter rate")?;
+                        let mut groups = vec![(
+                            fields.chunk("enemy group")?.1.to_string(),
+                            fields.num::<u32>("group weight")?,
+                        )];
+                        while fields.peek().is_some() {
+                            groups.push((
+                                fields.chunk("enemy group")?.1.to_string(),
+                                fields.num::<u32>("group weight")?,
+                            ));
+                        }
+                        encounters.push(EncounterZone { area, rate, groups });
+                        encounter_lines.push(line_no);
+                        continue;
+                    }
+                    let etype = match etype {
+                        "player" => EntityType::Player,
+                        "npc" => EntityType::Npc(fields.num::<usize>("dialog ID")?),
+                        "door" => {
+                            let to_room = fields.chunk("destination level")?.1;
+                            let to_x = fields.num::<u16>("destination x")?;
+                            let to_y = fields.num::<u16>("destination y")?;
+                            EntityType::Door(to_room.to_string(), to_x, to_y)
+                        }
+                        "enemy" => EntityType::Enemy(fields.chunk("enemy group")?.1.to_string()),
+                        "chest" => {
+                            let item = fields.chunk("item id")?.1;
+                            EntityType::Chest(item.to_string(), fields.num::<u32>("item count")?)
+                        }
+                        _ => {
+                            return Err(LevelParseError::UnknownEntity {
+                                line: line_no,
+                                col,
+                                etype: etype.to_string(),
+                            })
+                        }
+                    };
+                    let x = fields.num::<u16>("x coord")?;
+                    let y = fields.num::<u16>("y coord")?;
+                    fields.finish()?;
+                    starts.push((etype, Vec2 { x, y }));
+                    start_lines.push(line_no);
+                }
+                State::Done => return Err(fields.trailing().into()),
             }
         }
-        assert_ne!(name, None);
-        let name = name.unwrap();
-        assert_ne!(dims, None);
+        // Whatever sections we didn't get to are missing, so point at the end of the file.
+        State::Starts.check_complete(line_no + 1, dims, !legend.is_empty(), rows)?;
         let (w, h) = dims.unwrap();
-        assert!(!legend.is_empty());
-        assert_eq!(grid.len(), w as usize * h as usize);
         let mut tiles: Vec<(u8, TileData)> = legend.into_values().collect();
         tiles.sort_by_key(|(num, _)| *num);
-        Self {
-            name: name.to_string(),
-            grid: Grid::new(w as usize, h as usize, grid),
+        Ok(Self {
+            name: name.unwrap(),
+            layers: layers
+                .into_iter()
+                .map(|(name, depth, cells)| MapLayer {
+                    name,
+                    depth,
+                    grid: Grid::new(w as usize, h as usize, cells),
+                })
+                .collect(),
             tileset: Tileset {
                 tiles: tiles.into_iter().map(|(_num, val)| val).collect(),
+                frames,
             },
             starts,
+            start_lines,
+            encounters,
+            encounter_lines,
+            starts_end: line_no + 1,
+        })
+    }
+    /// How many sprites [`Level::render_into`] takes to draw `window`: one per cell of each layer
+    pub fn sprite_count(&self, window: Rect) -> usize {
+        self.layers.len() * window.w as usize * window.h as usize
+    }
+    /// The world position of the middle of the tile at `pos`.  Sprites are placed with y
+    /// going up from the bottom of the level in pixels, while grid positions count rows down
+    /// from the top, so everything drawn on the map should go through this.
+    pub fn tile_to_world(&self, pos: Vec2) -> [f32; 2] {
+        let row = self.height() - 1 - pos.y as usize;
+        [
+            (pos.x as usize * TILE_SZ + TILE_SZ / 2) as f32,
+            (row * TILE_SZ + TILE_SZ / 2) as f32,
+        ]
+    }
+    /// The tile a world position is on, if it's inside the level at all
+    pub fn world_to_tile(&self, [x, y]: [f32; 2]) -> Option<Vec2> {
+        if x < 0.0 || y < 0.0 {
+            return None;
         }
+        let x = (x / TILE_SZ as f32) as usize;
+        let row = (y / TILE_SZ as f32) as usize;
+        if x >= self.width() || row >= self.height() {
+            return None;
+        }
+        Some(Vec2 {
+            x: x as u16,
+            y: (self.height() - 1 - row) as u16,
+        })
     }
-    pub fn sprite_count(&self) -> usize {
-        self.grid.width() * self.grid.height()
-    }
-    pub fn render_into(&self, frend: &mut Renderer, offset: usize) -> usize {
-        let len = self.sprite_count();
-        let h = self.grid.height();
-        let (trfs, uvs) = frend.sprites_mut(0, offset..len);
-        let mut trfs = trfs.iter_mut();
-        let mut uvs = uvs.iter_mut();
-        for (y, row) in self.grid.row_iter().enumerate() {
-            for (x, tile) in row.iter().enumerate() {
-                let trf = trfs.next().unwrap();
-                let uv = uvs.next().unwrap();
-                // NOTE: we're converting from grid coordinates to "sprite center coordinates", so we have to flip y...
-                let y = h - y - 1;
-                *trf = Transform {
-                    // and multiply by tile sz *and* offset by half tile sz
-                    x: (x * TILE_SZ + TILE_SZ / 2) as f32,
-                    y: (y * TILE_SZ + TILE_SZ / 2) as f32,
-                    w: TILE_SZ as u16,
-                    h: TILE_SZ as u16,
-                    rot: 0.0,
-                };
-                *uv = self.tileset[*tile as usize].sheet_region;
-            }
+    // The sprite for each cell in `window` `time` seconds into the game, layer by layer and
+    // row by row, with empty cells left blank
+    fn tile_sprites(
+        &self,
+        window: Rect,
+        time: f32,
+    ) -> impl Iterator<Item = (Transform, SheetRegion)> + '_ {
+        let rows = window.y..(window.y + window.h).min(self.height() as u16);
+        let cols = window.x..(window.x + window.w).min(self.width() as u16);
+        self.layers.iter().flat_map(move |layer| {
+            let cols = cols.clone();
+            rows.clone()
+                .flat_map(move |y| cols.clone().map(move |x| Vec2 { x, y }))
+                .map(move |pos| {
+                    let Some(tile) = layer.grid.get(pos.x as usize, pos.y as usize).unwrap() else {
+                        return (Transform::ZERO, SheetRegion::ZERO);
+                    };
+                    let [x, y] = self.tile_to_world(pos);
+                    let trf = Transform {
+                        x,
+                        y,
+                        w: TILE_SZ as u16,
+                        h: TILE_SZ as u16,
+                        rot: 0.0,
+                    };
+                    let uv = self.tileset.sheet_region(*tile as usize, time);
+                    (trf, uv.with_depth(layer.depth))
+                })
+        })
+    }
+    /// Draws the tiles in `window` (e.g. from [`crate::camera::Camera::visible_tiles`])
+    /// into sprite group 0 starting at `offset`, returning how many sprites that took.
+    /// Animated tiles show whichever frame they're on `time` seconds into the game.
+    pub fn render_into(
+        &self,
+        frend: &mut Renderer,
+        offset: usize,
+        window: Rect,
+        time: f32,
+    ) -> usize {
+        let len = self.sprite_count(window);
+        let (trfs, uvs) = frend.sprites_mut(0, offset..offset + len);
+        let sprites = self.tile_sprites(window, time);
+        for ((trf, uv), sprite) in trfs.iter_mut().zip(uvs).zip(sprites) {
+            (*trf, *uv) = sprite;
         }
         len
     }
-    #[allow(dead_code)]
     pub fn name(&self) -> &str {
         &self.name
     }
+    pub fn width(&self) -> usize {
+        self.layers[0].grid.width()
+    }
+    pub fn height(&self) -> usize {
+        self.layers[0].grid.height()
+    }
+    pub fn layers(&self) -> &[MapLayer] {
+        &self.layers
+    }
     pub fn starts(&self) -> &[(EntityType, Vec2)] {
         &self.starts
     }
-    pub fn get_tile(&self, pos: Vec2) -> Option<&TileData> {
-        self.grid
-            .get(pos.x as usize, pos.y as usize)
-            .map(|t| &self.tileset[*t as usize])
+    pub fn player_start(&self) -> Option<Vec2> {
+        self.starts
+            .iter()
+            .find(|(etype, _)| *etype == EntityType::Player)
+            .map(|(_, pos)| *pos)
+    }
+    pub fn start_lines(&self) -> &[usize] {
+        &self.start_lines
+    }
+    pub fn encounters(&self) -> &[EncounterZone] {
+        &self.encounters
+    }
+    pub fn encounter_lines(&self) -> &[usize] {
+        &self.encounter_lines
+    }
+    /// The line just past the last start, where any missing ones would go
+    pub fn starts_end(&self) -> usize {
+        self.starts_end
+    }
+    /// What's at `pos` as far as getting around goes, taking all the layers together: it's
+    /// flags are everything any of their tiles there do.  The sheet region is the lowest
+    /// layer's tile.
+    pub fn get_tile(&self, pos: Vec2) -> Option<TileData> {
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let mut tiles = self
+            .layers
+            .iter()
+            .filter_map(|layer| *layer.grid.get(x, y).unwrap())
+            .map(|t| self.tileset[t as usize]);
+        let first = tiles.next().unwrap_or(TileData {
+            flags: TileFlags::default(),
+            sheet_region: SheetRegion::ZERO,
+            anim: None,
+        });
+        Some(tiles.fold(first, |under, over| TileData {
+            flags: under.flags.union(over.flags),
+            ..under
+        }))
+    }
+}
+
+impl FromStr for Level {
+    type Err = LevelParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const SMALL: &str = "small 3 2
+===
+. o 85 0 16 16
+# s 289 357 16 16
+===
+. . #
+# . .
+===
+player 1 0
+npc 0 1 1
+door small 0 0 2 1
+enemy slimes 0 0
+encounter 0 0 2 2 8 slimes 3 bats 1
+chest potion 2 2 0
+";
+    #[test]
+    fn test_parse_content() {
+        for (file, src) in [
+            ("level1", include_str!("../content/level1.txt")),
+            ("level2", include_str!("../content/level2.txt")),
+        ] {
+            let level = Level::parse(src).unwrap_or_else(|e| panic!("{file}:{e}"));
+            assert_eq!(level.name(), file);
+        }
+    }
+    #[test]
+    fn test_parse_small() {
+        let level = Level::parse(SMALL).unwrap();
+        assert_eq!(level.name(), "small");
+        let all = Rect {
+            x: 0,
+            y: 0,
+            w: 3,
+            h: 2,
+        };
+        assert_eq!(level.sprite_count(all), 6);
+        assert_eq!(level.layers().len(), 1);
+        assert_eq!(level.layers()[0].name(), "ground");
+        assert_eq!(level.layers()[0].depth(), 16);
+        assert!(level.get_tile(Vec2 { x: 2, y: 0 }).unwrap().flags.solid);
+        assert!(!level.get_tile(Vec2 { x: 1, y: 1 }).unwrap().flags.solid);
+        assert!(level.get_tile(Vec2 { x: 3, y: 0 }).is_none());
+        assert_eq!(
+            level.starts(),
+            &[
+                (EntityType::Player, Vec2 { x: 1, y: 0 }),
+                (EntityType::Npc(0), Vec2 { x: 1, y: 1 }),
+                (
+                    EntityType::Door("small".to_string(), 0, 0),
+                    Vec2 { x: 2, y: 1 }
+                ),
+                (EntityType::Enemy("slimes".to_string()), Vec2 { x: 0, y: 0 }),
+                (
+                    EntityType::Chest("potion".to_string(), 2),
+                    Vec2 { x: 2, y: 0 }
+                ),
+            ]
+        );
+        assert_eq!(
+            level.encounters(),
+            &[EncounterZone {
+                area: Rect {
+                    x: 0,
+                    y: 0,
+                    w: 2,
+                    h: 2
+                },
+                rate: 8,
+                groups: vec![("slimes".to_string(), 3), ("bats".to_string(), 1)]
+            }]
+        );
+        assert_eq!(level.encounter_lines(), &[13]);
+        assert_eq!(level.starts_end(), 15);
+    }
+    // A level that isn't one screen (15 tiles) tall, with a distinct tile on each row
+    fn tall(rows: usize) -> Level {
+        let mut src = format!("tall 2 {rows}\n===\n");
+        for row in 0..rows {
+            src += &format!("{row} o {} 0 16 16\n", row * 17);
+        }
+        src += "===\n";
+        for row in 0..rows {
+            src += &format!("{row} {row}\n");
+        }
+        src += "===\nplayer 0 0\n";
+        Level::parse(&src).unwrap()
+    }
+    #[test]
+    fn test_tile_to_world() {
+        let small = Level::parse(SMALL).unwrap();
+        assert_eq!(small.tile_to_world(Vec2 { x: 0, y: 0 }), [8.0, 24.0]);
+        assert_eq!(small.tile_to_world(Vec2 { x: 2, y: 1 }), [40.0, 8.0]);
+        for rows in [1, 2, 15, 23] {
+            let level = tall(rows);
+            let top = (rows * TILE_SZ) as f32;
+            // the top row is at the top of the level, and the bottom row at the bottom
+            assert_eq!(level.tile_to_world(Vec2 { x: 0, y: 0 }), [8.0, top - 8.0]);
+            let bottom = Vec2 {
+                x: 1,
+                y: rows as u16 - 1,
+            };
+            assert_eq!(level.tile_to_world(bottom), [24.0, 8.0]);
+            for y in 0..rows as u16 {
+                for x in 0..2 {
+                    let pos = Vec2 { x, y };
+                    assert_eq!(level.world_to_tile(level.tile_to_world(pos)), Some(pos));
+                }
+            }
+            assert_eq!(
+                level.world_to_tile([31.9, top - 0.1]),
+                Some(Vec2 { x: 1, y: 0 })
+            );
+            assert_eq!(level.world_to_tile([32.0, 8.0]), None);
+            assert_eq!(level.world_to_tile([8.0, top]), None);
+            assert_eq!(level.world_to_tile([-0.5, 8.0]), None);
+            assert_eq!(level.world_to_tile([8.0, -0.5]), None);
+        }
+    }
+    #[test]
+    fn test_tile_sprites() {
+        for rows in [2, 15, 23] {
+            let level = tall(rows);
+            let window = Rect {
+                x: 1,
+                y: 1,
+                w: 1,
+                h: rows as u16 - 1,
+            };
+            let sprites: Vec<_> = level.tile_sprites(window, 0.0).collect();
+            assert_eq!(sprites.len(), rows - 1);
+            for ((trf, uv), y) in sprites.into_iter().zip(1..) {
+                let pos = Vec2 { x: 1, y };
+                assert_eq!([trf.x, trf.y], level.tile_to_world(pos));
+                // each row has its own tile, so this is the right row's
+                assert_eq!(uv.x, y * 17);
+            }
+        }
+    }
+    // SMALL with a roof that's open underneath and a wall along its right edge
+    const LAYERED: &str = "small 3 2
+===
+. o 85 0 16 16
+# s 289 357 16 16
+^ o 306 357 16 16
+===
+layer floor 17
+. . #
+# . _
+===
+layer roof 13
+^ ^ _
+_ _ #
+===
+player 1 0
+";
+    #[test]
+    fn test_layers() {
+        let level = Level::parse(LAYERED).unwrap();
+        let layers: Vec<_> = level
+            .layers()
+            .iter()
+            .map(|layer| (layer.name(), layer.depth()))
+            .collect();
+        assert_eq!(layers, [("floor", 17), ("roof", 13)]);
+        // solid on either layer is solid
+        let solid = |x, y| level.get_tile(Vec2 { x, y }).unwrap().flags.solid;
+        assert!(!solid(0, 0));
+        assert!(!solid(1, 0));
+        assert!(solid(2, 0));
+        assert!(solid(0, 1));
+        assert!(solid(2, 1));
+        assert!(!solid(1, 1));
+        assert!(level.get_tile(Vec2 { x: 0, y: 2 }).is_none());
+        // the roof layer's tiles go on top, and empty cells draw nothing
+        let window = Rect {
+            x: 0,
+            y: 0,
+            w: 3,
+            h: 2,
+        };
+        assert_eq!(level.sprite_count(window), 12);
+        let sprites: Vec<_> = level.tile_sprites(window, 0.0).collect();
+        assert_eq!(sprites.len(), 12);
+        let depths: Vec<_> = sprites
+            .iter()
+            .map(|(trf, uv)| (trf.w != 0).then_some(uv.depth))
+            .collect();
+        assert_eq!(
+            depths[..6],
+            [Some(17), Some(17), Some(17), Some(17), Some(17), None]
+        );
+        assert_eq!(
+            depths[6..],
+            [Some(13), Some(13), None, None, None, Some(13)]
+        );
+        assert_eq!([sprites[6].0.x, sprites[6].0.y], [8.0, 24.0]);
+        // the first map section doesn't need a layer line
+        let level = Level::parse(&LAYERED.replace("layer floor 17\n", "")).unwrap();
+        assert_eq!(level.layers()[0].name(), "ground");
+        assert_eq!(level.layers()[1].name(), "roof");
+    }
+    #[test]
+    fn test_parse_flags() {
+        assert_eq!(parse_flags("o"), Some(TileFlags::default()));
+        let solid_water = TileFlags {
+            solid: true,
+            water: true,
+            ..Default::default()
+        };
+        assert_eq!(parse_flags("sw"), Some(solid_water));
+        assert_eq!(parse_flags("solid+water"), Some(solid_water));
+        assert_eq!(parse_flags("ws+o"), Some(solid_water));
+        assert_eq!(
+            parse_flags("o+noenc"),
+            Some(TileFlags {
+                no_encounter: true,
+                ..Default::default()
+            })
+        );
+        // enc on its own reads like encounters are on, so it isn't a way to turn them off
+        assert_eq!(parse_flags("o+enc"), None);
+        assert_eq!(parse_flags("noencounter"), parse_flags("n"));
+        assert_eq!(
+            parse_flags("cmn+damage:3+ledge:left"),
+            Some(TileFlags {
+                counter: true,
+                slow: true,
+                no_encounter: true,
+                damage: 3,
+                ledge: Some((-1, 0)),
+                ..Default::default()
+            })
+        );
+        for bad in [
+            "",
+            "x",
+            "o+",
+            "damage:lots",
+            "ledge:north",
+            "slow:2",
+            "s+wet",
+        ] {
+            assert_eq!(parse_flags(bad), None, "{bad}");
+        }
+        // a spot gets every layer's flags
+        let src = LAYERED.replace(". o", ". n").replace("^ o", "^ m+damage:2");
+        let level = Level::parse(&src).unwrap();
+        assert_eq!(
+            level.get_tile(Vec2 { x: 1, y: 0 }).unwrap().flags,
+            TileFlags {
+                no_encounter: true,
+                slow: true,
+                damage: 2,
+                ..Default::default()
+            }
+        );
+        // ledges only go one way
+        let ledge = parse_flags("ledge:down").unwrap();
+        assert!(ledge.can_enter((0, 1), false));
+        assert!(!ledge.can_enter((1, 0), false));
+        assert!(!ledge.can_leave((0, -1)));
+        assert!(ledge.can_leave((1, 0)));
+        let water = parse_flags("w").unwrap();
+        assert!(!water.can_enter((0, 1), false));
+        assert!(water.can_enter((0, 1), true));
+        assert!(!parse_flags("c").unwrap().can_enter((0, 1), true));
+    }
+    #[test]
+    fn test_animation() {
+        let src = SMALL.replace(". o 85 0 16 16", ". o 85 0 16 16 frames 0.25 102 0 119 0");
+        let level = Level::parse(&src).unwrap();
+        let anim = level.get_tile(Vec2 { x: 0, y: 0 }).unwrap().anim.unwrap();
+        assert_eq!((anim.count, anim.duration), (3, 0.25));
+        assert!(level.get_tile(Vec2 { x: 2, y: 0 }).unwrap().anim.is_none());
+        let window = Rect {
+            x: 0,
+            y: 0,
+            w: 3,
+            h: 2,
+        };
+        // every . is on the same frame, and # never changes
+        for (time, frame) in [(0.0, 85), (0.1, 85), (0.3, 102), (0.6, 119), (0.8, 85)] {
+            let xs: Vec<u16> = level
+                .tile_sprites(window, time)
+                .map(|(_, uv)| uv.x)
+                .collect();
+            assert_eq!(xs, [frame, frame, 289, 289, frame, frame], "{time}");
+        }
+        assert_eq!(
+            Level::parse(&src.replace("0.25", "0")).map(|_| ()),
+            Err(LevelParseError::BadNumber {
+                line: 3,
+                col: 23,
+                field: "frame duration",
+                text: "0".to_string()
+            })
+        );
+        assert_eq!(
+            Level::parse(&src.replace(" 119 0", " 119")).map(|_| ()),
+            Err(LevelParseError::MissingField {
+                line: 3,
+                col: 37,
+                field: "frame y"
+            })
+        );
+        assert_eq!(
+            Level::parse(&src.replace("frames 0.25", "frames 0.25 x")).map(|_| ()),
+            Err(LevelParseError::BadNumber {
+                line: 3,
+                col: 28,
+                field: "frame x",
+                text: "x".to_string()
+            })
+        );
+    }
+    #[test]
+    fn test_parse_errors() {
+        let err = |src: &str| Level::parse(src).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err(&SMALL.replace("bats 1", "bats")),
+            LevelParseError::MissingField {
+                line: 13,
+                col: 34,
+                field: "group weight"
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace(". . #", ". ? #")),
+            LevelParseError::UnknownSymbol {
+                line: 6,
+                col: 3,
+                sym: "?".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("# s", "# x")),
+            LevelParseError::BadFlag {
+                line: 4,
+                col: 3,
+                flag: "x".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("# . .", "# .")),
+            LevelParseError::WrongRowWidth {
+                line: 7,
+                col: 1,
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("# . .\n", "")),
+            LevelParseError::WrongRowCount {
+                line: 7,
+                col: 1,
+                expected: 2,
+                found: 1
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("npc 0 1 1", "npc zero 1 1")),
+            LevelParseError::BadNumber {
+                line: 10,
+                col: 5,
+                field: "dialog ID",
+                text: "zero".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("player 1 0", "player 1")),
+            LevelParseError::MissingField {
+                line: 9,
+                col: 9,
+                field: "y coord"
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("potion 2", "potion two")),
+            LevelParseError::BadNumber {
+                line: 14,
+                col: 14,
+                field: "item count",
+                text: "two".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("player 1 0", "player 1 0 7")),
+            LevelParseError::TrailingContent { line: 9, col: 12 }
+        );
+        assert_eq!(
+            err(&format!("{SMALL}===\nmore stuff\n")),
+            LevelParseError::TrailingContent { line: 16, col: 1 }
+        );
+        assert_eq!(
+            err(SMALL.split("===\n. .").next().unwrap()),
+            LevelParseError::MissingSection {
+                line: 5,
+                col: 1,
+                section: "map"
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("# s", "_ s")),
+            LevelParseError::ReservedSymbol {
+                line: 4,
+                col: 1,
+                sym: "_".to_string()
+            }
+        );
+        assert_eq!(
+            err(&LAYERED.replace("roof 13", "floor 13")),
+            LevelParseError::DuplicateLayer {
+                line: 11,
+                col: 7,
+                layer: "floor".to_string()
+            }
+        );
+        assert_eq!(
+            err(&LAYERED.replace("roof 13", "roof high")),
+            LevelParseError::BadNumber {
+                line: 11,
+                col: 12,
+                field: "layer depth",
+                text: "high".to_string()
+            }
+        );
+        assert_eq!(
+            err(&LAYERED.replace("_ _ #", "_ _")),
+            LevelParseError::WrongRowWidth {
+                line: 13,
+                col: 1,
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            err(&LAYERED.replace("_ _ #\n", "")),
+            LevelParseError::WrongRowCount {
+                line: 13,
+                col: 1,
+                expected: 2,
+                found: 1
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace("player", "monster")),
+            LevelParseError::UnknownEntity {
+                line: 9,
+                col: 1,
+                etype: "monster".to_string()
+            }
+        );
+        assert_eq!(
+            err(&SMALL.replace(". . #", ". . #\n. . .")),
+            LevelParseError::WrongRowCount {
+                line: 8,
+                col: 1,
+                expected: 2,
+                found: 3
+            }
+        );
     }
 }
@@ -0,0 +1,131 @@
+use frenderer::sprites::SheetRegion;
+
+pub mod battle;
+pub mod camera;
+pub mod classes;
+pub mod dialog;
+pub mod encounter;
+pub mod enemies;
+pub mod geom;
+pub mod grid;
+pub mod inventory;
+pub mod items;
+pub mod level;
+pub mod markup;
+pub mod menu;
+pub mod parse;
+pub mod party;
+pub mod roam;
+pub mod save;
+pub mod text;
+pub mod world;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EntityType {
+    Player,
+    // which dialog to use
+    Npc(usize),
+    // which level, x in dest level, y in dest level
+    Door(String, u16, u16),
+    // which enemy group to fight on contact
+    Enemy(String),
+    // which item and how many of it are inside
+    Chest(String, u32),
+}
+
+/// What a tile does to whoever's getting around the map
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TileFlags {
+    pub solid: bool,
+    // only the party can cross it, and only while carrying the item that lets them
+    pub water: bool,
+    // HP each party member loses on stepping onto it
+    pub damage: u32,
+    // can only be stepped onto going this way, and not left going back the other way
+    pub ledge: Option<(i32, i32)>,
+    // can't be walked onto, but whoever's on the other side can be talked to across it
+    pub counter: bool,
+    // the next step after stepping onto it takes a while
+    pub slow: bool,
+    // no random battles while standing on it, even inside an encounter zone
+    pub no_encounter: bool,
+}
+
+impl TileFlags {
+    /// Everything either set of flags does, e.g. for tiles stacked on top of each other
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            solid: self.solid || other.solid,
+            water: self.water || other.water,
+            damage: self.damage + other.damage,
+            ledge: self.ledge.or(other.ledge),
+            counter: self.counter || other.counter,
+            slow: self.slow || other.slow,
+            no_encounter: self.no_encounter || other.no_encounter,
+        }
+    }
+    /// Whether someone moving `dir` can step onto this tile; `swim` is whether they can
+    /// cross water
+    pub fn can_enter(&self, dir: (i32, i32), swim: bool) -> bool {
+        !self.solid
+            && !self.counter
+            && (swim || !self.water)
+            && self.ledge.is_none_or(|ledge| ledge == dir)
+    }
+    /// Whether this tile keeps everyone from standing on it, no matter what they're
+    /// carrying: solid ground and counters
+    pub fn blocks(&self) -> bool {
+        self.solid || self.counter
+    }
+    /// Whether someone standing on this tile can leave it moving `dir`
+    pub fn can_leave(&self, dir: (i32, i32)) -> bool {
+        self.ledge != Some((-dir.0, -dir.1))
+    }
+}
+
+/// A tile that cycles through several frames, each shown for `duration` seconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileAnim {
+    // where the frames start in the tileset's list of them, and how many there are
+    pub first: usize,
+    pub count: usize,
+    pub duration: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TileData {
+    pub flags: TileFlags,
+    // the first frame, if it's animated
+    pub sheet_region: SheetRegion,
+    pub anim: Option<TileAnim>,
+}
+
+#[derive(Debug)]
+pub struct Tileset {
+    tiles: Vec<TileData>,
+    // every animated tile's frames, one after another
+    frames: Vec<SheetRegion>,
+}
+impl Tileset {
+    /// How tile number `tile` looks `time` seconds into the game.  Every tile of the same
+    /// kind goes by the same clock, so they all animate together.
+    pub fn sheet_region(&self, tile: usize, time: f32) -> SheetRegion {
+        let data = &self.tiles[tile];
+        match data.anim {
+            Some(anim) => {
+                let frame = (time / anim.duration) as usize % anim.count;
+                self.frames[anim.first + frame]
+            }
+            None => data.sheet_region,
+        }
+    }
+}
+impl std::ops::Index<usize> for Tileset {
+    type Output = TileData;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.tiles[index]
+    }
+}
+
+// Feel free to change this if you use a different tilesheet
+pub const TILE_SZ: usize = 16;
@@ -1,56 +1,88 @@
-use assets_manager::{asset::Png, AssetCache};
+use assets_manager::{asset::Png, AssetCache, ReloadWatcher};
 use frenderer::{
     input::{Input, Key},
     sprites::{Camera2D, SheetRegion, Transform},
     wgpu, Renderer,
 };
-mod geom;
-mod grid;
-use geom::*;
-
-#[derive(Debug, PartialEq, Eq)]
-enum EntityType {
-    Player,
-    // which dialog to use
-    Npc(usize),
-    // which level, x in dest level, y in dest level
-    Door(String, u16, u16),
-}
-
-#[derive(Clone, Copy, Debug)]
-struct TileData {
-    solid: bool,
-    sheet_region: SheetRegion,
-}
-
-#[derive(Debug)]
-struct Tileset {
-    tiles: Vec<TileData>,
-}
-impl std::ops::Index<usize> for Tileset {
-    type Output = TileData;
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.tiles[index]
-    }
-}
+use graphical::battle::{Action, Battle, Combatant, Outcome, Side, SkillKind, SKILLS};
+use graphical::camera::Camera;
+use graphical::classes::Classes;
+use graphical::dialog::{Advance, Conversation, Dialogs, Effect};
+use graphical::encounter::roll_encounter;
+use graphical::enemies::Enemies;
+use graphical::geom::*;
+use graphical::inventory::Inventory;
+use graphical::items::{ItemDef, ItemKind, Items, Slot};
+use graphical::level::Level;
+use graphical::markup::{self, Context, Marked, Part};
+use graphical::menu::{Menu, MenuEvent, CANCEL, CONFIRM, LINE_H};
+use graphical::party::{Member, Trail, MAX_PARTY};
+use graphical::roam::roam_step;
+use graphical::save::{slot_path, Save, SavedLevel, SavedMember, SAVE_SLOTS};
+use graphical::text::{wrap, TextMetrics, Typewriter};
+use graphical::world::World;
+use graphical::{EntityType, TileData, TILE_SZ};
+use rand::{rngs::SmallRng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
-mod level;
-use level::Level;
 struct Game {
-    levels: Vec<Level>,
+    cache: &'static AssetCache,
+    // asset name, level name, and change watcher for each level file in the world
+    level_assets: Vec<(String, String, ReloadWatcher<'static>)>,
+    dialog_watcher: ReloadWatcher<'static>,
+    enemies_watcher: ReloadWatcher<'static>,
+    items_watcher: ReloadWatcher<'static>,
+    classes_watcher: ReloadWatcher<'static>,
+    tilesheet_watcher: ReloadWatcher<'static>,
+    // follows the player around the map
+    camera: Camera,
+    levels: HashMap<String, Level>,
+    // where the world starts, and where you wake up after losing a battle
+    start_level: String,
     mode: GameMode,
-    dialogs: Vec<String>,
-    active_dialog: Option<usize>,
-    current_level: usize,
-    npcs: Vec<(Vec2, usize)>,
+    dialogs: Dialogs,
+    enemies: Enemies,
+    items: Items,
+    classes: Classes,
+    // index into npcs of whoever we're talking to
+    talking_to: Option<usize>,
+    // an enemy group to fight once the current dialog node is done
+    pending_battle: Option<String>,
+    party: Vec<Member>,
+    // for random encounters
+    rng: SmallRng,
+    flags: HashSet<String>,
+    inventory: Inventory,
+    // dialog, menus and so on over the map, with the one that gets the keys last
+    ui: Vec<Layer>,
+    level_states: HashMap<String, LevelState>,
+    current_level: String,
+    npcs: Vec<Npc>,
+    roamers: Vec<Roamer>,
+    // the level start index of the roamer being fought, if any
+    fighting: Option<usize>,
     doors: Vec<(String, Vec2, Vec2)>,
+    chests: Vec<Chest>,
     player: Vec2, // player, entities, other dynamic info here
+    // where everyone else in the party is, following along behind the player
+    trail: Trail,
+    // which way the player last moved, for interacting with whatever's in front of them
+    facing: (i32, i32),
+    // how long until the player can take another step, after stepping onto something slow
+    move_wait: f32,
+    // seconds spent on the map, which animated tiles all go by
+    tile_clock: f32,
+    // where save slots are kept
+    save_dir: PathBuf,
     font: frenderer::bitfont::BitFont,
+    // the same font in another color, for highlighted dialog
+    highlight_font: frenderer::bitfont::BitFont,
+    // how wide the font's characters are, for wrapping text
+    metrics: TextMetrics,
     window: frenderer::nineslice::NineSlice,
 }
 
-// Feel free to change this if you use a different tilesheet
-const TILE_SZ: usize = 16;
 const W: usize = 320;
 const H: usize = 240;
 
@@ -58,37 +90,444 @@ const WIND_W: f32 = 288.0;
 const WIND_H: f32 = 112.0;
 const WIND_X: f32 = (W as f32 - WIND_W) / 2.0;
 const WIND_Y: f32 = H as f32 - 16.0 - WIND_H;
+// lines of text that fit in the dialog window under the speaker's name
+const DLG_LINES: usize = 4;
+// characters per second dialog is typed out at, or None to show each page all at once
+const TEXT_SPEED: Option<f32> = Some(40.0);
+
+// dialog choices go in a smaller box hanging below the right side of the dialog window
+const CHOICE_W: f32 = 128.0;
+const CHOICE_X: f32 = WIND_X + WIND_W - CHOICE_W;
+
+// Keys for opening menus; menu.rs has the ones for using them
+const INVENTORY: Key = Key::KeyI;
+const PAUSE: Key = Key::Enter;
+// how far back each UI layer is from the one above it; a layer's windows and text take up
+// this many depths, and three layers still fit in front of the map's sprites
+const UI_LAYER_DEPTH: u16 = 4;
+// the map scrolls around in sprite group 0, and the UI stays put on top of it in this one
+const UI_GROUP: usize = 1;
+const UI_CAMERA: Camera2D = Camera2D {
+    screen_pos: [0.0, 0.0],
+    screen_size: [W as f32, H as f32],
+};
+// how far (in pixels) the player can get from the middle of the screen before it scrolls,
+// and how quickly it catches up (see Camera::with_smoothing), or None to keep up exactly
+const CAMERA_DEAD_ZONE: [f32; 2] = [48.0, 32.0];
+const CAMERA_SMOOTHING: Option<f32> = Some(12.0);
+// extra rows and columns of tiles drawn around the edges of the screen
+const TILE_MARGIN: usize = 1;
+
+// battle screen layout: messages along the top, commands and party status along the bottom
+const MSG_X: f32 = 8.0;
+const MSG_Y: f32 = H as f32 - 48.0;
+const MSG_W: f32 = W as f32 - 16.0;
+const MSG_H: f32 = 40.0;
+const CMD_X: f32 = 8.0;
+const CMD_Y: f32 = 8.0;
+const CMD_W: f32 = 112.0;
+const CMD_H: f32 = 88.0;
+const STATUS_X: f32 = CMD_X + CMD_W + 8.0;
+const STATUS_W: f32 = W as f32 - STATUS_X - 8.0;
+const COMMANDS: [&str; 5] = ["Attack", "Skill", "Item", "Defend", "Flee"];
+
+// inventory menu layout: the item list, a box for what to do with the picked item and
+// who to use it on, and the selected item's description along the bottom
+const INV_X: f32 = 16.0;
+const INV_Y: f32 = 56.0;
+const INV_W: f32 = 176.0;
+const INV_H: f32 = 168.0;
+const INV_SIDE_X: f32 = INV_X + INV_W + 8.0;
+const INV_SIDE_W: f32 = 112.0;
+const DESC_X: f32 = 16.0;
+const DESC_Y: f32 = 8.0;
+const DESC_W: f32 = W as f32 - 32.0;
+const DESC_H: f32 = 40.0;
+const INV_ACTIONS: [&str; 2] = ["Use", "Drop"];
+
+// the pause menu sits in the top right corner, and the status page fills the screen
+// with one panel per party member
+const PAUSE_W: f32 = 96.0;
+const PAUSE_X: f32 = W as f32 - 8.0 - PAUSE_W;
+const PAUSE_OPTIONS: [&str; 4] = ["Items", "Status", "Equip", "Save"];
+const STATUS_PANEL_H: f32 = 56.0;
+const BAR_W: f32 = 64.0;
+// solid patches of color on the tilesheet, stretched out into HP and MP bars
+const BAR_BACK: SheetRegion = SheetRegion::new(0, 635, 3, 0, 2, 2);
+const HP_BAR: SheetRegion = SheetRegion::new(0, 26, 103, 0, 2, 2);
+const MP_BAR: SheetRegion = SheetRegion::new(0, 615, 60, 0, 2, 2);
+// the equip screen: the member's slots across the top, then what could go in the
+// picked slot on the left and how the member's stats would change on the right
+const EQUIP_SLOTS_H: f32 = 32.0 + 12.0 * 4.0;
+const EQUIP_STATS_X: f32 = 200.0;
+const EQUIP_STATS_W: f32 = W as f32 - 8.0 - EQUIP_STATS_X;
+// how many choices for a slot fit on screen at once
+const EQUIP_CHOICE_ROWS: usize = 8;
 
-const DLG_X: f32 = (W as f32 - WIND_W) / 2.0 + 16.0;
-const DLG_Y: f32 = H as f32 - 16.0 - 16.0;
+// title screen layout: its menu, or the list of save slots (which saving uses too),
+// in the middle of the screen
+const TITLE_OPTIONS: [&str; 2] = ["New Game", "Load"];
+const TITLE_W: f32 = 112.0;
+const TITLE_X: f32 = (W as f32 - TITLE_W) / 2.0;
+const SLOT_W: f32 = 224.0;
+const SLOT_X: f32 = (W as f32 - SLOT_W) / 2.0;
+const MENU_Y: f32 = 64.0;
 
 const DOOR: SheetRegion = SheetRegion::new(0, 561, 34, 15, TILE_SZ as i16, TILE_SZ as i16);
 const NPC: SheetRegion = SheetRegion::new(0, 0, 714, 14, TILE_SZ as i16, TILE_SZ as i16);
-const PLAYER: SheetRegion = SheetRegion::new(0, 0, 578, 14, TILE_SZ as i16, TILE_SZ as i16);
+const CHEST: SheetRegion = SheetRegion::new(0, 255, 119, 15, TILE_SZ as i16, TILE_SZ as i16);
+const CHEST_OPEN: SheetRegion = SheetRegion::new(0, 238, 119, 15, TILE_SZ as i16, TILE_SZ as i16);
 
 // TODO: point: (style) add two more rooms
 // TODO: point: (style) transition animation between rooms
-// TODO: point: (structure) NPC dialog contains yes/no or other choices (modify dialog.txt and use numbers to point to the "next" dialog in either case)
-// TODO: point: (structure) NPC dialog can have the effect of giving the player items or spawning new enemies or starting a battle or whatever (modify dialog.txt as needed, maybe update the speaker's dlg index afterwards so they don't give the item twice!)
-// TODO: point: (structure) combat screen with turn taking combat with enemies (entered via random chance or by bumping into enemies)
-// TODO: point: (style) display of player and enemy stats during battle
 // TODO: point: (style) transition animation in and out of combat
-// TODO: point: (structure) inventory menu and getting items from chests/battle
-// TODO: point: (structure) statistics menu and stat growth through battles or field events
-// TODO: point: (structure) multiple party members who trail you around and act in battle
+// TODO: point: (structure) getting items from battle
+// TODO: point: (structure) stat growth through field events
 
 enum GameMode {
+    Title(TitleScreen),
     Map,
-    Battle,
+    Battle(Box<BattleScreen>),
+}
+
+// The title screen, where a new game gets started or a saved one picked up again
+#[derive(Default)]
+struct TitleScreen {
+    cursor: usize,
+    // the save slots, once Load has been picked
+    loading: Option<SlotMenu>,
+    // why the last save picked couldn't be loaded
+    message: Option<String>,
+}
+
+// A list of the save slots and what's in each one, read in when the list is opened
+struct SlotMenu {
+    cursor: usize,
+    // None for an empty slot, or why a slot's save can't be loaded
+    saves: Vec<Option<Result<Save, String>>>,
+}
+
+// A battle plus the state of its menus
+struct BattleScreen {
+    battle: Battle,
+    menu: BattleMenu,
+    cursor: usize,
+    // shown until confirmed, which moves on to the next turn
+    message: Option<String>,
+    // whether the message is the one saying how the battle ended
+    over: bool,
+    // what each party member gets for winning
+    xp: u32,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum BattleMenu {
+    Commands,
+    Skills,
+    Items,
+    // picking who to use an attack, skill or item on
+    Targets(Pending),
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Pending {
+    Attack,
+    // index into SKILLS
+    Skill(usize),
+    // index into the usable items
+    Item(usize),
+}
+
+impl BattleScreen {
+    fn new(battle: Battle, xp: u32) -> Self {
+        Self {
+            battle,
+            menu: BattleMenu::Commands,
+            cursor: 0,
+            message: Some("Enemies draw near!".to_string()),
+            over: false,
+            xp,
+        }
+    }
+    // the party member whose turn it is, if it's the party's turn
+    fn actor(&self) -> Option<&Combatant> {
+        self.battle
+            .current()
+            .map(|c| &self.battle.combatants()[c])
+            .filter(|c| c.side == Side::Party)
+    }
+    // items that can be used right now, with how many of each there are
+    fn usable_items<'i>(
+        inventory: &'i Inventory,
+        items: &'i Items,
+    ) -> Vec<(&'i str, &'i ItemDef, u32)> {
+        inventory
+            .stacks()
+            .iter()
+            .filter_map(|(id, count)| items.get(id).map(|def| (id.as_str(), def, *count)))
+            .filter(|(_, def, _)| def.usable())
+            .collect()
+    }
+    fn targets(&self, pending: &Pending) -> Vec<usize> {
+        match pending {
+            Pending::Skill(skill)
+                if matches!(
+                    SKILLS[*skill].kind,
+                    SkillKind::Heal(_) | SkillKind::Boost(_)
+                ) =>
+            {
+                self.battle.standing(Side::Party)
+            }
+            Pending::Item(_) => self.battle.standing(Side::Party),
+            _ => self.battle.standing(Side::Enemy),
+        }
+    }
+    // The options in the current menu, and whether each one can be picked
+    fn options(&self, inventory: &Inventory, items: &Items) -> Vec<(String, bool)> {
+        let Some(actor) = self.actor() else {
+            return vec![];
+        };
+        match &self.menu {
+            BattleMenu::Commands => COMMANDS
+                .iter()
+                .map(|cmd| {
+                    let enabled = match *cmd {
+                        "Skill" => !actor.skills.is_empty(),
+                        "Item" => !Self::usable_items(inventory, items).is_empty(),
+                        _ => true,
+                    };
+                    (cmd.to_string(), enabled)
+                })
+                .collect(),
+            BattleMenu::Skills => actor
+                .skills
+                .iter()
+                .map(|&s| {
+                    (
+                        format!("{} {}", SKILLS[s].name, SKILLS[s].mp),
+                        self.battle.can_use(s),
+                    )
+                })
+                .collect(),
+            BattleMenu::Items => Self::usable_items(inventory, items)
+                .into_iter()
+                .map(|(_, item, count)| (format!("{} x{count}", item.name), true))
+                .collect(),
+            BattleMenu::Targets(pending) => self
+                .targets(pending)
+                .into_iter()
+                .map(|c| (self.battle.combatants()[c].name.clone(), true))
+                .collect(),
+        }
+    }
+}
+
+// Something drawn over the map, like a conversation or a menu.  Only the top layer gets
+// the keys, and the map only gets them too if nothing up sees them first or blocks it.
+enum Layer {
+    Dialog(DialogBox),
+    // a box of text, like what was in a chest, up until it's dismissed
+    Notice(String),
+    Inventory(InventoryMenu),
+    Pause(PauseMenu),
+}
+
+impl Layer {
+    // whether the map (walking, enemies moving, opening menus) waits while this is up
+    fn blocks_map(&self) -> bool {
+        // notices get out of the way, and go away once they're dismissed
+        !matches!(self, Layer::Notice(_))
+    }
+    // whether the layers under this one still get drawn; the map always does
+    fn shows_below(&self) -> bool {
+        match self {
+            Layer::Dialog(_) | Layer::Notice(_) => true,
+            // the inventory covers up the pause menu it was opened from
+            Layer::Inventory(_) => false,
+            Layer::Pause(menu) => matches!(menu.page, PausePage::Main),
+        }
+    }
+}
+
+// A conversation, along with how much of its current page is showing.  Pages too long
+// for the window are split into parts that each fill it.
+struct DialogBox {
+    convo: Conversation,
+    // which windowful of the page is up
+    part: usize,
+    typewriter: Typewriter,
+}
+
+// The inventory menu opened from the map
+#[derive(Default)]
+struct InventoryMenu {
+    cursor: usize,
+    // index into INV_ACTIONS, once an item's been picked
+    action: Option<usize>,
+    // which party member to use the item on, once Use has been picked
+    target: Option<usize>,
+    // shown in place of the item description until the next key press
+    message: Option<String>,
+}
+
+// The pause menu opened from the map
+#[derive(Default)]
+struct PauseMenu {
+    cursor: usize,
+    page: PausePage,
+}
+
+// What the pause menu is showing
+#[derive(Default)]
+enum PausePage {
+    #[default]
+    Main,
+    Status,
+    Equip(EquipMenu),
+    Save(SlotMenu),
+}
+
+#[derive(Default)]
+struct EquipMenu {
+    // index into the party
+    member: usize,
+    // index into Slot::ALL
+    slot: usize,
+    // index into Game::equip_choices, once a slot's been picked
+    choice: Option<usize>,
+}
+
+// Windows (x, y, w, h), menus, lines of text, and other sprites making up a screen's UI
+#[derive(Default)]
+struct UiParts<'a> {
+    windows: Vec<[f32; 4]>,
+    menus: Vec<Menu<'a>>,
+    texts: Vec<(String, [f32; 2])>,
+    // text in the highlight font
+    highlights: Vec<(String, [f32; 2])>,
+    sprites: Vec<(Transform, SheetRegion)>,
+}
+
+impl UiParts<'_> {
+    fn sprite_count(&self, window: &frenderer::nineslice::NineSlice) -> usize {
+        self.windows
+            .iter()
+            .map(|[_, _, w, h]| window.sprite_count(*w, *h))
+            .sum::<usize>()
+            + self.menus.iter().map(Menu::sprite_count).sum::<usize>()
+            + self
+                .texts
+                .iter()
+                .chain(self.highlights.iter())
+                .map(|(text, _)| text.len())
+                .sum::<usize>()
+            + self.sprites.len()
+    }
+    // Draws everything, pushed back by `depth`, returning how many sprites it used
+    fn draw(
+        &self,
+        font: &frenderer::bitfont::BitFont,
+        highlight_font: &frenderer::bitfont::BitFont,
+        window: &frenderer::nineslice::NineSlice,
+        depth: u16,
+        sprite_posns: &mut [Transform],
+        sprite_gfx: &mut [SheetRegion],
+    ) -> usize {
+        let mut used = 0;
+        for [x, y, w, h] in self.windows.iter().copied() {
+            used += window.draw(
+                &mut sprite_posns[used..],
+                &mut sprite_gfx[used..],
+                x,
+                y,
+                w,
+                h,
+                1,
+            );
+        }
+        for menu in self.menus.iter() {
+            used += menu.draw_into(&mut sprite_posns[used..], &mut sprite_gfx[used..]);
+        }
+        let texts = self.texts.iter().map(|text| (font, text));
+        let highlights = self.highlights.iter().map(|text| (highlight_font, text));
+        for (font, (text, pos)) in texts.chain(highlights) {
+            let (_, n) = font.draw_text(
+                &mut sprite_posns[used..],
+                &mut sprite_gfx[used..],
+                text,
+                *pos,
+                0,
+                8.0,
+            );
+            used += n;
+        }
+        for (trf, uv) in self.sprites.iter() {
+            sprite_posns[used] = *trf;
+            sprite_gfx[used] = *uv;
+            used += 1;
+        }
+        for uv in sprite_gfx[..used].iter_mut() {
+            uv.depth += depth;
+        }
+        used
+    }
+}
+
+// Whatever has changed about a level since it was loaded, kept around while we're elsewhere
+#[derive(Default)]
+struct LevelState {
+    // level start index of an npc -> the dialog it uses now, for npcs that have been rebound
+    npc_dialogs: HashMap<usize, usize>,
+    // level start indices of enemies that have been beaten
+    defeated_enemies: HashSet<usize>,
+    // level start indices of chests that have been emptied
+    opened_chests: HashSet<usize>,
+}
+
+struct Npc {
+    pos: Vec2,
+    dialog: usize,
+    // which of the level's starts it came from
+    start: usize,
+}
+
+struct Chest {
+    pos: Vec2,
+    item: String,
+    count: u32,
+    // which of the level's starts it came from
+    start: usize,
+    opened: bool,
+}
+
+// An enemy walking around the map, which starts a battle when it touches the player
+struct Roamer {
+    pos: Vec2,
+    group: String,
+    // which of the level's starts it came from
+    start: usize,
+    // seconds until it moves again
+    wait: f32,
 }
 
+// seconds between enemy steps, and how long they hold off after the player runs away
+const ROAM_INTERVAL: f32 = 0.5;
+const FLEE_GRACE: f32 = 2.0;
+// seconds before the player can step off a slow tile
+const SLOW_STEP: f32 = 0.4;
+// the key item that lets the party cross water tiles
+const WATER_ITEM: &str = "raft";
+
 fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     let source =
         assets_manager::source::FileSystem::new("content").expect("Couldn't load resources");
     #[cfg(target_arch = "wasm32")]
     let source = assets_manager::source::Embedded::from(assets_manager::source::embed!("content"));
-    let cache = assets_manager::AssetCache::with_source(source);
+    // The game holds on to the cache so it can notice edited files and reload them while running,
+    // so the cache has to live for the rest of the program.
+    let cache: &'static AssetCache = Box::leak(Box::new(AssetCache::with_source(source)));
+    cache.enhance_hot_reloading();
 
     let drv = frenderer::Driver::new(
         winit::window::WindowBuilder::new()
@@ -104,7 +543,7 @@ fn main() {
     let mut acc = 0.0;
     drv.run_event_loop::<(), _>(
         move |window, mut frend| {
-            let game = Game::new(&mut frend, &cache);
+            let game = Game::new(&mut frend, cache);
             (window, game, frend)
         },
         move |event, target, (window, ref mut game, ref mut frend)| {
@@ -134,6 +573,7 @@ fn main() {
                     // I'm not using it here to keep the starter code small.
                     acc += elapsed;
                     now = std::time::Instant::now();
+                    game.reload_changed_assets(frend);
                     // While we have time to spend
                     while acc >= DT {
                         // simulate a frame
@@ -154,72 +594,144 @@ fn main() {
     .expect("event loop error");
 }
 
+fn load_tile_texture(renderer: &Renderer, cache: &AssetCache) -> wgpu::Texture {
+    let tile_handle = cache
+        .load::<Png>("tilesheet")
+        .expect("Couldn't load tilesheet img");
+    let tile_img = tile_handle.read().0.to_rgba8();
+    renderer.create_array_texture(
+        &[&tile_img],
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        tile_img.dimensions(),
+        Some("tiles-sprites"),
+    )
+}
+
+// Reads and parses one of the text files in content/, like `load_content(cache, "dialog", Dialogs::parse)`
+fn load_content<T, E: std::fmt::Display>(
+    cache: &AssetCache,
+    asset: &str,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<T, String> {
+    let handle = cache
+        .load::<String>(asset)
+        .map_err(|e| format!("couldn't access {asset}.txt: {e}"))?;
+    // bind the result so the read guard is dropped before we return
+    let parsed = parse(&handle.read()).map_err(|e| format!("{asset}.txt:{e}"));
+    parsed
+}
+
 impl Game {
-    fn new(renderer: &mut Renderer, cache: &AssetCache) -> Self {
-        let tile_handle = cache
-            .load::<Png>("tilesheet")
-            .expect("Couldn't load tilesheet img");
-        let tile_img = tile_handle.read().0.to_rgba8();
-        let tile_tex = renderer.create_array_texture(
-            &[&tile_img],
-            wgpu::TextureFormat::Rgba8UnormSrgb,
-            tile_img.dimensions(),
-            Some("tiles-sprites"),
+    fn new(renderer: &mut Renderer, cache: &'static AssetCache) -> Self {
+        let tile_tex = load_tile_texture(renderer, cache);
+        let world = World::parse(
+            &cache
+                .load::<String>("world")
+                .expect("Couldn't access world.txt")
+                .read(),
+        )
+        .unwrap_or_else(|e| panic!("world.txt:{e}"));
+        let mut levels = HashMap::new();
+        let mut level_assets = vec![];
+        for asset in world.levels() {
+            let level = load_content(cache, asset, Level::parse).unwrap_or_else(|e| panic!("{e}"));
+            let name = level.name().to_string();
+            if let Some(other) = levels.insert(name.clone(), level) {
+                panic!("{asset}.txt reuses level name {}", other.name());
+            }
+            let watcher = cache.load_expect::<String>(asset).reload_watcher();
+            level_assets.push((asset.clone(), name, watcher));
+        }
+        let current_level = world.start().to_string();
+        assert!(
+            levels.contains_key(&current_level),
+            "world.txt starts in unknown level {current_level}"
         );
-        let levels = vec![
-            Level::from_str(
-                &cache
-                    .load::<String>("level1")
-                    .expect("Couldn't access level1.txt")
-                    .read(),
-            ),
-            Level::from_str(
-                &cache
-                    .load::<String>("level2")
-                    .expect("Couldn't access level2.txt")
-                    .read(),
-            ),
-        ];
-        let current_level = 0;
-        let dialogs = cache
-            .load::<String>("dialog")
-            .expect("couldn't access dialog.txt")
-            .read()
-            .lines()
-            .map(str::to_string)
-            .collect();
-        // TODO: will need to parse the dialogs specially if you add yes/no or item rewards or whatever, probably into a Dialog struct instead of a string
-        let camera = Camera2D {
-            screen_pos: [0.0, 0.0],
-            screen_size: [W as f32, H as f32],
-        };
-        let sprite_estimate =
-            levels[current_level].sprite_count() + levels[current_level].starts().len();
+        let dialogs =
+            load_content(cache, "dialog", Dialogs::parse).unwrap_or_else(|e| panic!("{e}"));
+        let enemies =
+            load_content(cache, "enemies", Enemies::parse).unwrap_or_else(|e| panic!("{e}"));
+        let items = load_content(cache, "items", Items::parse).unwrap_or_else(|e| panic!("{e}"));
+        let classes =
+            load_content(cache, "classes", Classes::parse).unwrap_or_else(|e| panic!("{e}"));
+        let hero = classes
+            .get("hero")
+            .map(|class| Member::new("Hero", "hero", class))
+            .expect("classes.txt has no hero class");
+        let mut camera = Camera::new(W as f32, H as f32)
+            .with_dead_zone(CAMERA_DEAD_ZONE[0], CAMERA_DEAD_ZONE[1]);
+        if let Some(rate) = CAMERA_SMOOTHING {
+            camera = camera.with_smoothing(rate);
+        }
+        let sprite_estimate = (W / TILE_SZ + 2 * TILE_MARGIN + 1)
+            * (H / TILE_SZ + 2 * TILE_MARGIN + 1)
+            + levels[&current_level].starts().len();
         renderer.sprite_group_add(
             &tile_tex,
             vec![Transform::ZERO; sprite_estimate],
             vec![SheetRegion::ZERO; sprite_estimate],
-            camera,
+            camera.camera2d(),
         );
-        let player_start = *levels[current_level]
-            .starts()
-            .iter()
-            .find(|(t, _)| *t == EntityType::Player)
-            .map(|(_, ploc)| ploc)
+        renderer.sprite_group_add(&tile_tex, vec![], vec![], UI_CAMERA);
+        let player_start = levels[&current_level]
+            .player_start()
             .expect("Start level doesn't put the player anywhere");
         // TODO initialize your game here
+        let font = frenderer::bitfont::BitFont::with_sheet_region(
+            ' '..='~',
+            SheetRegion::new(0, 0, 738, 0, 288, 765),
+            8,
+            8,
+            1,
+            1,
+        );
         let mut game = Game {
+            cache,
+            level_assets,
+            dialog_watcher: cache.load_expect::<String>("dialog").reload_watcher(),
+            enemies_watcher: cache.load_expect::<String>("enemies").reload_watcher(),
+            items_watcher: cache.load_expect::<String>("items").reload_watcher(),
+            classes_watcher: cache.load_expect::<String>("classes").reload_watcher(),
+            tilesheet_watcher: cache.load_expect::<Png>("tilesheet").reload_watcher(),
+            camera,
             levels,
+            start_level: current_level.clone(),
             current_level,
             dialogs,
-            mode: GameMode::Map,
-            active_dialog: None,
+            enemies,
+            items,
+            classes,
+            mode: GameMode::Title(TitleScreen::default()),
+            talking_to: None,
+            pending_battle: None,
+            // set RPG_SEED to get the same encounters every time
+            rng: match std::env::var("RPG_SEED").ok().and_then(|s| s.parse().ok()) {
+                Some(seed) => SmallRng::seed_from_u64(seed),
+                None => SmallRng::from_entropy(),
+            },
+            party: vec![hero],
+            flags: HashSet::new(),
+            inventory: Inventory::default(),
+            ui: vec![],
+            level_states: HashMap::new(),
             npcs: vec![],
+            roamers: vec![],
+            fighting: None,
             doors: vec![],
+            chests: vec![],
             player: player_start,
-            font: frenderer::bitfont::BitFont::with_sheet_region(
+            trail: Trail::default(),
+            facing: (0, 1),
+            move_wait: 0.0,
+            tile_clock: 0.0,
+            metrics: TextMetrics::measure(&font, 8.0),
+            // set RPG_SAVE_DIR to keep saves somewhere other than saves/
+            save_dir: std::env::var("RPG_SAVE_DIR")
+                .map_or_else(|_| PathBuf::from("saves"), PathBuf::from),
+            font,
+            highlight_font: frenderer::bitfont::BitFont::with_sheet_region(
                 ' '..='~',
-                SheetRegion::new(0, 0, 738, 0, 288, 765),
+                SheetRegion::new(0, 0, 801, 0, 288, 27),
                 8,
                 8,
                 1,
@@ -257,114 +769,1582 @@ impl Game {
     fn enter_level(&mut self, player_pos: Vec2) {
         self.doors.clear();
         self.npcs.clear();
+        self.roamers.clear();
+        self.chests.clear();
         self.player = player_pos;
-        let level = &self.levels[self.current_level];
-        for (etype, pos) in level.starts().iter() {
+        // followers come through the door right behind the player, then spread back out
+        self.trail.gather(player_pos, self.party.len() - 1);
+        let level = &self.levels[&self.current_level];
+        let state = self.level_states.get(&self.current_level);
+        let defeated = state.map(|state| &state.defeated_enemies);
+        let opened = state.map(|state| &state.opened_chests);
+        for (start, (etype, pos)) in level.starts().iter().enumerate() {
             match etype {
+                EntityType::Enemy(group) => {
+                    if !defeated.is_some_and(|beaten| beaten.contains(&start)) {
+                        self.roamers.push(Roamer {
+                            pos: *pos,
+                            group: group.clone(),
+                            start,
+                            wait: ROAM_INTERVAL,
+                        });
+                    }
+                }
                 EntityType::Player => {}
                 EntityType::Door(rm, x, y) => {
                     self.doors.push((rm.clone(), Vec2 { x: *x, y: *y }, *pos))
                 }
-                EntityType::Npc(dlg) => self.npcs.push((*pos, *dlg)),
+                EntityType::Npc(dlg) => self.npcs.push(Npc {
+                    pos: *pos,
+                    dialog: *dlg,
+                    start,
+                }),
+                EntityType::Chest(item, count) => self.chests.push(Chest {
+                    pos: *pos,
+                    item: item.clone(),
+                    count: *count,
+                    start,
+                    opened: opened.is_some_and(|opened| opened.contains(&start)),
+                }),
+            }
+        }
+        if let Some(state) = self.level_states.get(&self.current_level) {
+            for npc in self.npcs.iter_mut() {
+                if let Some(&dlg) = state.npc_dialogs.get(&npc.start) {
+                    npc.dialog = dlg;
+                }
+            }
+        }
+        let (target, bounds) = (self.level().tile_to_world(self.player), self.level_bounds());
+        self.camera.snap(target, bounds);
+    }
+    // Starts a conversation at the given node with the given npc
+    fn start_dialog(&mut self, node: usize, npc: usize) {
+        let dbox = self.dialog_box(Conversation::new(node));
+        self.ui.push(Layer::Dialog(dbox));
+        self.talking_to = Some(npc);
+        self.apply_effects(node);
+    }
+    // Everything about the game that's worth saving
+    fn snapshot(&self) -> Save {
+        let sorted = |set: &HashSet<usize>| {
+            let mut sorted: Vec<usize> = set.iter().copied().collect();
+            sorted.sort();
+            sorted
+        };
+        let mut levels: Vec<(String, SavedLevel)> = self
+            .level_states
+            .iter()
+            .map(|(name, state)| {
+                let mut npc_dialogs: Vec<(usize, usize)> = state
+                    .npc_dialogs
+                    .iter()
+                    .map(|(&npc, &dlg)| (npc, dlg))
+                    .collect();
+                npc_dialogs.sort();
+                let state = SavedLevel {
+                    npc_dialogs,
+                    defeated_enemies: sorted(&state.defeated_enemies),
+                    opened_chests: sorted(&state.opened_chests),
+                };
+                (name.clone(), state)
+            })
+            .collect();
+        levels.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut flags: Vec<String> = self.flags.iter().cloned().collect();
+        flags.sort();
+        Save {
+            level: self.current_level.clone(),
+            player: self.player,
+            facing: self.facing,
+            followers: self.trail.spots().to_vec(),
+            party: self.party.iter().map(SavedMember::new).collect(),
+            inventory: self.inventory.stacks().to_vec(),
+            flags,
+            levels,
+        }
+    }
+    // Picks up where a save left off, unless someone in it has a class that's gone or it's
+    // somewhere that isn't there anymore
+    fn load(&mut self, save: &Save) -> Result<(), String> {
+        let mut save = save.clone();
+        let level = self
+            .levels
+            .get(&save.level)
+            .ok_or(format!("there's no level called {} anymore", save.level))?;
+        save.fit(level, |id| self.dialogs.contains(id))?;
+        let party = save
+            .party
+            .iter()
+            .map(|saved| {
+                saved.member(&self.classes, &self.items).ok_or(format!(
+                    "{} is a {}, which isn't in classes.txt",
+                    saved.name, saved.class
+                ))
+            })
+            .collect::<Result<Vec<Member>, String>>()?;
+        self.party = party;
+        self.inventory = Inventory::default();
+        for (item, count) in save.inventory.iter() {
+            self.inventory.add(item, *count);
+        }
+        self.flags = save.flags.iter().cloned().collect();
+        self.level_states = save
+            .levels
+            .iter()
+            .map(|(name, state)| {
+                let state = LevelState {
+                    npc_dialogs: state.npc_dialogs.iter().copied().collect(),
+                    defeated_enemies: state.defeated_enemies.iter().copied().collect(),
+                    opened_chests: state.opened_chests.iter().copied().collect(),
+                };
+                (name.clone(), state)
+            })
+            .collect();
+        self.ui.clear();
+        self.talking_to = None;
+        self.pending_battle = None;
+        self.fighting = None;
+        self.mode = GameMode::Map;
+        self.current_level = save.level.clone();
+        self.enter_level(save.player);
+        self.facing = save.facing;
+        self.trail = Trail::with_spots(save.followers.clone());
+        self.trail.fit(save.player, self.party.len() - 1);
+        Ok(())
+    }
+    fn save_to(&self, slot: usize) -> Result<(), String> {
+        std::fs::create_dir_all(&self.save_dir).map_err(|e| e.to_string())?;
+        std::fs::write(slot_path(&self.save_dir, slot), self.snapshot().to_string())
+            .map_err(|e| e.to_string())
+    }
+    // Reads in what's in every save slot
+    fn slot_menu(&self) -> SlotMenu {
+        let saves = (1..=SAVE_SLOTS)
+            .map(|slot| {
+                let text = std::fs::read_to_string(slot_path(&self.save_dir, slot)).ok()?;
+                let save = Save::parse(&text, |name| self.levels.contains_key(name));
+                Some(save.map_err(|e| format!("slot{slot}.txt:{e}")))
+            })
+            .collect();
+        SlotMenu { cursor: 0, saves }
+    }
+    // Cleans up after a conversation whose layer has just been closed
+    fn end_dialog(&mut self) {
+        self.talking_to = None;
+        if let Some(group) = self.pending_battle.take() {
+            self.start_battle(&group);
+        }
+    }
+    // Puts whatever's in a chest into the inventory, if it hasn't been emptied already
+    fn open_chest(&mut self, chest: usize) {
+        let chest = &mut self.chests[chest];
+        if chest.opened {
+            return;
+        }
+        chest.opened = true;
+        self.inventory.add(&chest.item, chest.count);
+        let name = self
+            .items
+            .get(&chest.item)
+            .map_or(chest.item.as_str(), |def| &def.name);
+        self.ui.push(Layer::Notice(if chest.count == 1 {
+            format!("Got {name}!")
+        } else {
+            format!("Got {name} x{}!", chest.count)
+        }));
+        self.level_states
+            .entry(self.current_level.clone())
+            .or_default()
+            .opened_chests
+            .insert(chest.start);
+    }
+    fn fight_roamer(&mut self, roamer: usize) {
+        let group = self.roamers[roamer].group.clone();
+        self.start_battle(&group);
+        if let GameMode::Battle(_) = self.mode {
+            self.fighting = Some(self.roamers[roamer].start);
+        }
+    }
+    fn move_roamers(&mut self, dt: f32) {
+        for i in 0..self.roamers.len() {
+            self.roamers[i].wait -= dt;
+            if self.roamers[i].wait > 0.0 {
+                continue;
+            }
+            self.roamers[i].wait = ROAM_INTERVAL;
+            let (npcs, doors, roamers, chests) =
+                (&self.npcs, &self.doors, &self.roamers, &self.chests);
+            let blocked = |pos: Vec2| {
+                npcs.iter().any(|npc| npc.pos == pos)
+                    || doors.iter().any(|(_, _, p)| *p == pos)
+                    || roamers.iter().any(|r| r.pos == pos)
+                    || chests.iter().any(|c| c.pos == pos)
+            };
+            let dest = roam_step(
+                &self.levels[&self.current_level],
+                self.roamers[i].pos,
+                self.player,
+                blocked,
+                &mut self.rng,
+            );
+            if dest == self.player {
+                self.fight_roamer(i);
+                return;
+            }
+            self.roamers[i].pos = dest;
+        }
+    }
+    fn start_battle(&mut self, group: &str) {
+        let Some(enemies) = self.enemies.group(group) else {
+            eprintln!("there's no enemy group {group} to fight");
+            return;
+        };
+        let party = self.party.iter().map(|member| member.combatant()).collect();
+        let battle = Battle::new(party, enemies);
+        let xp = self.enemies.group_xp(group);
+        self.mode = GameMode::Battle(Box::new(BattleScreen::new(battle, xp)));
+    }
+    // Brings the party's HP and MP back to the map, hands out XP if they won, and sends
+    // them home if they lost
+    fn end_battle(&mut self) {
+        let GameMode::Battle(screen) = std::mem::replace(&mut self.mode, GameMode::Map) else {
+            return;
+        };
+        for (member, fighter) in self.party.iter_mut().zip(screen.battle.party()) {
+            member.sync(&fighter);
+        }
+        let fought = self.fighting.take();
+        match screen.battle.outcome() {
+            Some(Outcome::Victory) => {
+                self.award_xp(screen.xp);
+                if let Some(start) = fought {
+                    self.level_states
+                        .entry(self.current_level.clone())
+                        .or_default()
+                        .defeated_enemies
+                        .insert(start);
+                    self.roamers.retain(|r| r.start != start);
+                }
+            }
+            Some(Outcome::Fled) => {
+                for roamer in self.roamers.iter_mut() {
+                    roamer.wait = FLEE_GRACE;
+                }
+            }
+            _ => {}
+        }
+        if screen.battle.outcome() == Some(Outcome::Defeat) {
+            for member in self.party.iter_mut() {
+                member.restore();
+            }
+            self.current_level = self.start_level.clone();
+            let start = self.level().player_start().unwrap_or(self.player);
+            self.enter_level(start);
+        }
+    }
+    // Gives everyone still standing the XP, with a notice for anyone who levels up
+    fn award_xp(&mut self, xp: u32) {
+        let mut level_ups = vec![];
+        for member in self.party.iter_mut().filter(|member| member.hp > 0) {
+            let Some(class) = self.classes.get(&member.class) else {
+                eprintln!("{} has no class {} to grow in", member.name, member.class);
+                continue;
+            };
+            if let Some(up) = member.gain_xp(xp, class) {
+                level_ups.push(format!(
+                    "{} reached level {}!\n{}",
+                    member.name,
+                    up.level,
+                    up.describe()
+                ));
+            }
+        }
+        if !level_ups.is_empty() {
+            self.ui.push(Layer::Notice(level_ups.join("\n")));
+        }
+    }
+    // Carries out everything that happens on entering a dialog node
+    fn apply_effects(&mut self, node: usize) {
+        let Some(dlg) = self.dialogs.get(node) else {
+            return;
+        };
+        for effect in dlg.effects.iter() {
+            match effect {
+                Effect::Give(item, count) => self.inventory.add(item, *count),
+                Effect::Take(item, count) => {
+                    self.inventory.remove(item, *count);
+                }
+                Effect::Set(flag) => {
+                    self.flags.insert(flag.clone());
+                }
+                Effect::Clear(flag) => {
+                    self.flags.remove(flag);
+                }
+                Effect::Battle(group) => self.pending_battle = Some(group.clone()),
+                Effect::Rebind(dlg) => {
+                    if let Some(npc) = self.talking_to.and_then(|npc| self.npcs.get_mut(npc)) {
+                        npc.dialog = *dlg;
+                        self.level_states
+                            .entry(self.current_level.clone())
+                            .or_default()
+                            .npc_dialogs
+                            .insert(npc.start, *dlg);
+                    }
+                }
+                Effect::Join(class_id, name) => {
+                    if self.party.len() >= MAX_PARTY {
+                        continue;
+                    }
+                    let Some(class) = self.classes.get(class_id) else {
+                        eprintln!("{name} can't join as a {class_id}, which isn't a class");
+                        continue;
+                    };
+                    self.party.push(Member::new(name, class_id, class));
+                    self.trail.fit(self.player, self.party.len() - 1);
+                }
             }
         }
     }
+    // Picks up any content or tilesheet changes made on disk since the last call.
+    fn reload_changed_assets(&mut self, frend: &mut Renderer) {
+        if self.tilesheet_watcher.reloaded() {
+            let tile_tex = load_tile_texture(frend, self.cache);
+            // there's no way to swap a sprite group's texture, so replace the whole groups
+            // (freed slots get recycled last-in first-out, so free the UI's first)
+            let map_count = frend.sprite_group_size(0);
+            let ui_count = frend.sprite_group_size(UI_GROUP);
+            frend.sprite_group_remove(UI_GROUP);
+            frend.sprite_group_remove(0);
+            frend.sprite_group_add(
+                &tile_tex,
+                vec![Transform::ZERO; map_count],
+                vec![SheetRegion::ZERO; map_count],
+                self.camera.camera2d(),
+            );
+            frend.sprite_group_add(
+                &tile_tex,
+                vec![Transform::ZERO; ui_count],
+                vec![SheetRegion::ZERO; ui_count],
+                UI_CAMERA,
+            );
+        }
+        if self.enemies_watcher.reloaded() {
+            match load_content(self.cache, "enemies", Enemies::parse) {
+                Ok(enemies) => self.enemies = enemies,
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+        if self.classes_watcher.reloaded() {
+            match load_content(self.cache, "classes", Classes::parse) {
+                Ok(classes) => self.classes = classes,
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+        if self.items_watcher.reloaded() {
+            match load_content(self.cache, "items", Items::parse) {
+                Ok(items) => self.items = items,
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+        if self.dialog_watcher.reloaded() {
+            match load_content(self.cache, "dialog", Dialogs::parse) {
+                Ok(dialogs) => self.dialogs = dialogs,
+                Err(e) => eprintln!("{e}"),
+            }
+            // the conversation we're in might not make sense anymore
+            let dialogs = &self.dialogs;
+            let before = self.ui.len();
+            self.ui.retain(|layer| match layer {
+                Layer::Dialog(dbox) => dialogs
+                    .get(dbox.convo.node())
+                    .is_some_and(|dlg| dbox.convo.page() < dlg.pages.len()),
+                _ => true,
+            });
+            if self.ui.len() != before {
+                self.talking_to = None;
+                self.pending_battle = None;
+            }
+        }
+        let mut current_changed = false;
+        for (asset, name, watcher) in self.level_assets.iter_mut() {
+            if !watcher.reloaded() {
+                continue;
+            }
+            // A half-edited file shouldn't crash the game; keep the old version until it parses
+            let level = match load_content(self.cache, asset, Level::parse) {
+                Ok(level) => level,
+                Err(e) => {
+                    eprintln!("{e}");
+                    continue;
+                }
+            };
+            if level.name() != name && self.levels.contains_key(level.name()) {
+                eprintln!("{asset}.txt reuses level name {}", level.name());
+                continue;
+            }
+            self.levels.remove(name);
+            if *name == self.current_level {
+                self.current_level = level.name().to_string();
+                current_changed = true;
+            }
+            *name = level.name().to_string();
+            self.levels.insert(name.clone(), level);
+        }
+        if current_changed {
+            // whoever we were talking to might not be there anymore, or be someone else now
+            self.ui.retain(|layer| !matches!(layer, Layer::Dialog(_)));
+            self.talking_to = None;
+            self.pending_battle = None;
+            // stay put if that's still a legal spot, otherwise go back to the level's start
+            let swim = self.inventory.count(WATER_ITEM) > 0;
+            let player = match self.level().get_tile(self.player) {
+                Some(TileData { flags, .. }) if flags.can_enter(self.facing, swim) => self.player,
+                _ => self.level().player_start().unwrap_or(self.player),
+            };
+            self.enter_level(player);
+        }
+    }
     fn level(&self) -> &Level {
-        &self.levels[self.current_level]
+        &self.levels[&self.current_level]
+    }
+    // The part of the level that's on screen, give or take a margin
+    fn visible_tiles(&self) -> Rect {
+        let level = self.level();
+        self.camera
+            .visible_tiles(TILE_SZ, level.width(), level.height(), TILE_MARGIN)
+    }
+    // How big the level is in pixels, for keeping the camera inside it
+    fn level_bounds(&self) -> [f32; 2] {
+        let level = self.level();
+        [
+            (level.width() * TILE_SZ) as f32,
+            (level.height() * TILE_SZ) as f32,
+        ]
+    }
+    // A tile-sized sprite on the map at `pos`
+    fn tile_sprite(&self, pos: Vec2) -> Transform {
+        let [x, y] = self.level().tile_to_world(pos);
+        Transform {
+            w: TILE_SZ as u16,
+            h: TILE_SZ as u16,
+            x,
+            y,
+            rot: 0.0,
+        }
     }
     fn sprite_count(&self) -> usize {
-        // TODO: do something different in battle mode
-        self.level().sprite_count()
+        self.level().sprite_count(self.visible_tiles())
             + self.npcs.len()
+            + self.roamers.len()
             + self.doors.len()
-            + 1
-            + self
-                .active_dialog
-                .map(|dlg| self.dialogs[dlg].len() + self.window.sprite_count(WIND_W, WIND_H))
-                .unwrap_or(0)
+            + self.chests.len()
+            + self.party.len()
+    }
+    fn ui_sprite_count(&self) -> usize {
+        self.visible_layers()
+            .iter()
+            .map(|layer| self.layer_ui(layer).sprite_count(&self.window))
+            .sum()
+    }
+    // The layers that get drawn: the top one, and everything under it down to the
+    // first one that covers up what's below
+    fn visible_layers(&self) -> &[Layer] {
+        let bottom = self
+            .ui
+            .iter()
+            .rposition(|layer| !layer.shows_below())
+            .unwrap_or(0);
+        &self.ui[bottom..]
+    }
+    fn layer_ui(&self, layer: &Layer) -> UiParts<'_> {
+        match layer {
+            Layer::Dialog(dbox) => self.dialog_ui(dbox),
+            Layer::Notice(notice) => self.notice_ui(notice),
+            Layer::Inventory(menu) => self.inventory_ui(menu),
+            Layer::Pause(menu) => self.pause_ui(menu),
+        }
+    }
+    // The choices on the current page of a conversation, in a box hanging below the dialog
+    fn dialog_choices(&self, convo: Conversation) -> Option<Menu<'_>> {
+        let choices = convo.choices(&self.dialogs, &self.flags);
+        if choices.is_empty() {
+            return None;
+        }
+        let h = Menu::height_for(choices.len());
+        Some(
+            Menu::new(
+                [CHOICE_X, WIND_Y - h, CHOICE_W, h],
+                &self.window,
+                &self.font,
+            )
+            .with_options(choices.iter().map(|choice| (choice.text.as_str(), true)))
+            .with_selected(convo.cursor()),
+        )
+    }
+    // The current page of a conversation with its markup filled in, wrapped to the dialog
+    // window and split into windowfuls; "\n" in the dialog file starts a new line.  Pages
+    // with broken markup show up as they're written (the linter points those out).
+    fn dialog_parts(&self, convo: Conversation) -> Vec<Part> {
+        let page = self.dialogs[convo.node()].pages[convo.page()].replace("\\n", "\n");
+        let context = Context {
+            party: &self.party,
+            inventory: &self.inventory,
+            flags: &self.flags,
+        };
+        let marked = markup::parse(&page).map_or_else(
+            |_| Marked::plain(&page),
+            |markup| Marked::render(&markup, &context),
+        );
+        marked.layout(WIND_W - 32.0, self.metrics, DLG_LINES)
+    }
+    // A conversation at the start of its current page
+    fn dialog_box(&self, convo: Conversation) -> DialogBox {
+        DialogBox {
+            convo,
+            part: 0,
+            typewriter: self.dialog_parts(convo)[0].typewriter(TEXT_SPEED),
+        }
+    }
+    // The dialog box with the speaker's name and as much of the page as has been typed
+    // out, then either an arrow if there's more to the page or any choices once it's done
+    fn dialog_ui(&self, dbox: &DialogBox) -> UiParts<'_> {
+        let convo = dbox.convo;
+        let dlg = &self.dialogs[convo.node()];
+        let parts = self.dialog_parts(convo);
+        let part = dbox.part.min(parts.len() - 1);
+        let more = part + 1 < parts.len();
+        let done = dbox.typewriter.done();
+        let text_box = Menu::new([WIND_X, WIND_Y, WIND_W, WIND_H], &self.window, &self.font)
+            .with_title(dlg.speaker.as_str());
+        let top = WIND_Y + WIND_H - 16.0;
+        let (mut texts, mut highlights) = (vec![], vec![]);
+        for (i, runs) in parts[part]
+            .runs(dbox.typewriter.shown())
+            .into_iter()
+            .enumerate()
+        {
+            let mut x = WIND_X + 16.0;
+            for (run, highlight) in runs {
+                let width = self.metrics.width(&run);
+                let pos = [x, top - 16.0 - LINE_H * i as f32];
+                if highlight {
+                    highlights.push((run, pos));
+                } else {
+                    texts.push((run, pos));
+                }
+                x += width;
+            }
+        }
+        if done && more {
+            texts.push(("v".to_string(), [WIND_X + WIND_W - 24.0, WIND_Y + 16.0]));
+        }
+        let choices = if done && !more {
+            self.dialog_choices(convo)
+        } else {
+            None
+        };
+        UiParts {
+            texts,
+            highlights,
+            menus: std::iter::once(text_box).chain(choices).collect(),
+            ..Default::default()
+        }
+    }
+    // Whatever the party member whose turn it is can pick from right now
+    fn battle_commands(&self, screen: &BattleScreen) -> Option<Menu<'_>> {
+        if screen.message.is_some() || screen.actor().is_none() {
+            return None;
+        }
+        Some(
+            Menu::new([CMD_X, CMD_Y, CMD_W, CMD_H], &self.window, &self.font)
+                .with_options(screen.options(&self.inventory, &self.items))
+                .with_selected(screen.cursor),
+        )
+    }
+    fn battle_ui(&self, screen: &BattleScreen) -> UiParts<'_> {
+        let battle = &screen.battle;
+        let windows = vec![
+            [MSG_X, MSG_Y, MSG_W, MSG_H],
+            [STATUS_X, CMD_Y, STATUS_W, CMD_H],
+        ];
+        let mut texts = vec![];
+        let mut sprites = vec![];
+        let targets = match &screen.menu {
+            BattleMenu::Targets(pending) if screen.message.is_none() => screen.targets(pending),
+            _ => vec![],
+        };
+        let target = targets.get(screen.cursor).copied();
+        // enemies spread out across the middle of the screen, the party in a row below them
+        for (side, y, scale) in [(Side::Enemy, 156.0, 2), (Side::Party, 116.0, 1)] {
+            let members = battle.side(side);
+            for (i, &c) in members.iter().enumerate() {
+                let combatant = &battle.combatants()[c];
+                if !combatant.alive() {
+                    continue;
+                }
+                let x = W as f32 * (i + 1) as f32 / (members.len() + 1) as f32;
+                let size = (TILE_SZ * scale) as u16;
+                sprites.push((
+                    Transform {
+                        w: size,
+                        h: size,
+                        x,
+                        y,
+                        rot: 0.0,
+                    },
+                    combatant.sprite,
+                ));
+                if target == Some(c) || battle.current() == Some(c) && side == Side::Party {
+                    texts.push((">".to_string(), [x - size as f32 / 2.0 - 12.0, y + 4.0]));
+                }
+            }
+        }
+        let message = match (&screen.message, screen.actor()) {
+            (Some(message), _) => message.clone(),
+            (None, Some(actor)) => format!("What will {} do?", actor.name),
+            (None, None) => String::new(),
+        };
+        texts.push((message, [MSG_X + 16.0, MSG_Y + MSG_H - 16.0]));
+        let mut y = CMD_Y + CMD_H - 16.0;
+        texts.push(("     HP      MP".to_string(), [STATUS_X + 16.0, y]));
+        for c in battle.side(Side::Party) {
+            let member = &battle.combatants()[c];
+            y -= 12.0;
+            texts.push((
+                format!(
+                    "{:<5}{:>3}/{:<3} {:>2}/{:<2}",
+                    member.name,
+                    member.hp,
+                    member.stats().max_hp,
+                    member.mp,
+                    member.stats().max_mp
+                ),
+                [STATUS_X + 16.0, y],
+            ));
+        }
+        UiParts {
+            windows,
+            menus: self.battle_commands(screen).into_iter().collect(),
+            texts,
+            sprites,
+            ..Default::default()
+        }
+    }
+    fn inventory_list(&self, menu: &InventoryMenu) -> Menu<'_> {
+        Menu::new([INV_X, INV_Y, INV_W, INV_H], &self.window, &self.font)
+            .with_options(self.inventory.stacks().iter().map(|(id, count)| {
+                let name = self.items.get(id).map_or(id.as_str(), |def| &def.name);
+                (format!("{name:<14}x{count}"), true)
+            }))
+            .with_selected(menu.cursor)
+    }
+    // what to do with the picked item, in the top of the side column
+    fn inventory_actions(&self, menu: &InventoryMenu) -> Option<Menu<'_>> {
+        let h = Menu::height_for(INV_ACTIONS.len());
+        menu.action.map(|action| {
+            Menu::new(
+                [INV_SIDE_X, INV_Y + INV_H - h, INV_SIDE_W, h],
+                &self.window,
+                &self.font,
+            )
+            .with_options(INV_ACTIONS.iter().map(|label| (*label, true)))
+            .with_selected(action)
+        })
+    }
+    // who to use the picked item on, in the bottom of the side column
+    fn inventory_targets(&self, menu: &InventoryMenu) -> Option<Menu<'_>> {
+        let h = Menu::height_for(self.party.len());
+        menu.target.map(|target| {
+            Menu::new([INV_SIDE_X, INV_Y, INV_SIDE_W, h], &self.window, &self.font)
+                .with_options(self.party.iter().map(|member| {
+                    (
+                        format!(
+                            "{:<5}{:>3}/{}",
+                            member.name,
+                            member.hp,
+                            member.stats().max_hp
+                        ),
+                        true,
+                    )
+                }))
+                .with_selected(target)
+        })
+    }
+    fn inventory_ui(&self, menu: &InventoryMenu) -> UiParts<'_> {
+        let stacks = self.inventory.stacks();
+        let mut texts = vec![];
+        if stacks.is_empty() {
+            texts.push((
+                "(nothing)".to_string(),
+                [INV_X + 16.0, INV_Y + INV_H - 16.0],
+            ));
+        }
+        let desc = match &menu.message {
+            Some(message) => message.clone(),
+            None => stacks
+                .get(menu.cursor)
+                .and_then(|(id, _)| self.items.get(id))
+                .map(|def| def.desc.clone())
+                .unwrap_or_default(),
+        };
+        texts.push((desc, [DESC_X + 16.0, DESC_Y + DESC_H - 16.0]));
+        UiParts {
+            windows: vec![[DESC_X, DESC_Y, DESC_W, DESC_H]],
+            menus: std::iter::once(self.inventory_list(menu))
+                .chain(self.inventory_actions(menu))
+                .chain(self.inventory_targets(menu))
+                .collect(),
+            texts,
+            ..Default::default()
+        }
+    }
+    fn notice_ui(&self, notice: &str) -> UiParts<'_> {
+        // grow the box upwards to fit however many lines there are
+        let lines = notice.lines().count().max(1);
+        let h = MSG_H + 12.0 * (lines - 1) as f32;
+        let y = H as f32 - 8.0 - h;
+        UiParts {
+            windows: vec![[MSG_X, y, MSG_W, h]],
+            texts: notice
+                .lines()
+                .enumerate()
+                .map(|(i, line)| {
+                    (
+                        line.to_string(),
+                        [MSG_X + 16.0, y + h - 16.0 - 12.0 * i as f32],
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+    fn title_list(&self, title: &TitleScreen) -> Menu<'_> {
+        let h = Menu::height_for(TITLE_OPTIONS.len());
+        Menu::new([TITLE_X, MENU_Y, TITLE_W, h], &self.window, &self.font)
+            .with_options(TITLE_OPTIONS.map(|option| (option, true)))
+            .with_selected(title.cursor)
+    }
+    // The save slots, to save into (any of them) or load from (ones with a save that works)
+    fn slot_list(&self, slots: &SlotMenu, loading: bool) -> Menu<'_> {
+        let h = Menu::height_for(SAVE_SLOTS + 1);
+        Menu::new([SLOT_X, MENU_Y, SLOT_W, h], &self.window, &self.font)
+            .with_title(if loading { "Load" } else { "Save" })
+            .with_options(slots.saves.iter().enumerate().map(|(i, save)| {
+                let label = match save {
+                    None => "(empty)".to_string(),
+                    Some(Ok(save)) => save.summary(),
+                    Some(Err(_)) => "(can't load)".to_string(),
+                };
+                (
+                    format!("{} {label}", i + 1),
+                    !loading || matches!(save, Some(Ok(_))),
+                )
+            }))
+            .with_selected(slots.cursor)
+    }
+    // The title menu or the save slots, and why loading didn't work if it didn't
+    fn title_ui(&self, title: &TitleScreen) -> UiParts<'_> {
+        let menu = match &title.loading {
+            Some(slots) => self.slot_list(slots, true),
+            None => self.title_list(title),
+        };
+        // a slot that can't be loaded says why while it's selected
+        let broken = title
+            .loading
+            .as_ref()
+            .and_then(|slots| match &slots.saves[slots.cursor] {
+                Some(Err(e)) => Some(e.as_str()),
+                _ => None,
+            });
+        let message = title
+            .message
+            .as_deref()
+            .or(broken)
+            .map_or(vec![], |message| wrap(message, MSG_W - 32.0, self.metrics));
+        UiParts {
+            menus: vec![menu],
+            ..if message.is_empty() {
+                UiParts::default()
+            } else {
+                self.notice_ui(&message.join("\n"))
+            }
+        }
+    }
+    // which pause menu options can be picked right now
+    fn pause_options(&self) -> Vec<(&'static str, bool)> {
+        PAUSE_OPTIONS.iter().map(|&option| (option, true)).collect()
+    }
+    fn pause_ui(&self, menu: &PauseMenu) -> UiParts<'_> {
+        match &menu.page {
+            PausePage::Main => {}
+            PausePage::Status => return self.status_ui(),
+            PausePage::Equip(equip) => return self.equip_ui(equip),
+            PausePage::Save(slots) => {
+                return UiParts {
+                    menus: vec![self.slot_list(slots, false)],
+                    ..Default::default()
+                }
+            }
+        }
+        UiParts {
+            menus: vec![self.pause_list(menu)],
+            ..Default::default()
+        }
+    }
+    fn pause_list(&self, menu: &PauseMenu) -> Menu<'_> {
+        let h = Menu::height_for(PAUSE_OPTIONS.len());
+        Menu::new(
+            [PAUSE_X, H as f32 - 8.0 - h, PAUSE_W, h],
+            &self.window,
+            &self.font,
+        )
+        .with_options(self.pause_options())
+        .with_selected(menu.cursor)
+    }
+    // What could go in a slot: taking off what's there, or anything in the inventory that fits
+    fn equip_choices(&self, slot: Slot) -> Vec<Option<String>> {
+        std::iter::once(None)
+            .chain(
+                self.inventory
+                    .stacks()
+                    .iter()
+                    .filter(|(id, _)| {
+                        self.items
+                            .get(id)
+                            .is_some_and(|def| def.kind == ItemKind::Equipment(slot))
+                    })
+                    .map(|(id, _)| Some(id.clone())),
+            )
+            .collect()
+    }
+    fn item_name<'s>(&'s self, id: &'s str) -> &'s str {
+        self.items.get(id).map_or(id, |def| &def.name)
+    }
+    // the member's slots and what's in them, with left and right to flip between members
+    fn equip_slots(&self, menu: &EquipMenu) -> Menu<'_> {
+        let member = &self.party[menu.member];
+        let top = H as f32 - 8.0;
+        let header = if self.party.len() > 1 {
+            format!("< {} >", member.name)
+        } else {
+            member.name.clone()
+        };
+        Menu::new(
+            [8.0, top - EQUIP_SLOTS_H, W as f32 - 16.0, EQUIP_SLOTS_H],
+            &self.window,
+            &self.font,
+        )
+        .with_title(header)
+        .with_options(Slot::ALL.iter().map(|slot| {
+            let worn = member.equipped(*slot).map_or("-", |id| self.item_name(id));
+            (format!("{:<10}{worn}", slot.name()), true)
+        }))
+        .with_selected(menu.slot)
+    }
+    // what could go in the picked slot, once one's been picked
+    fn equip_choice_list(&self, menu: &EquipMenu) -> Option<Menu<'_>> {
+        let choice = menu.choice?;
+        let choices = self.equip_choices(Slot::ALL[menu.slot]);
+        let below = H as f32 - 8.0 - EQUIP_SLOTS_H - 8.0;
+        let h = Menu::height_for(choices.len().min(EQUIP_CHOICE_ROWS));
+        Some(
+            Menu::new(
+                [8.0, below - h, EQUIP_STATS_X - 16.0, h],
+                &self.window,
+                &self.font,
+            )
+            .with_options(choices.iter().map(|id| {
+                let label = id.as_deref().map_or("(take off)", |id| self.item_name(id));
+                (label, true)
+            }))
+            .with_selected(choice),
+        )
+    }
+    fn equip_ui(&self, menu: &EquipMenu) -> UiParts<'_> {
+        let member = &self.party[menu.member];
+        let top = H as f32 - 8.0;
+        let below = top - EQUIP_SLOTS_H - 8.0;
+        let mut ui = UiParts {
+            windows: vec![[EQUIP_STATS_X, below - 92.0, EQUIP_STATS_W, 92.0]],
+            menus: std::iter::once(self.equip_slots(menu))
+                .chain(self.equip_choice_list(menu))
+                .collect(),
+            ..Default::default()
+        };
+        // the stats the member would have with the highlighted choice on instead
+        let slot = Slot::ALL[menu.slot];
+        let mut preview = member.clone();
+        if let Some(choice) = menu.choice {
+            preview.equip(
+                slot,
+                self.equip_choices(slot)[choice].as_deref(),
+                &self.items,
+            );
+        }
+        let (now, then) = (member.stats(), preview.stats());
+        for (i, (label, now, then)) in [
+            ("HP", now.max_hp, then.max_hp),
+            ("MP", now.max_mp, then.max_mp),
+            ("ATK", now.attack, then.attack),
+            ("DEF", now.defense, then.defense),
+            ("SPD", now.speed, then.speed),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let delta = match then.cmp(&now) {
+                std::cmp::Ordering::Greater => format!("+{}", then - now),
+                std::cmp::Ordering::Less => format!("-{}", now - then),
+                std::cmp::Ordering::Equal => String::new(),
+            };
+            ui.texts.push((
+                format!("{label:<4}{then:>3} {delta}"),
+                [EQUIP_STATS_X + 12.0, below - 16.0 - 12.0 * i as f32],
+            ));
+        }
+        ui
+    }
+    // A panel for each party member with their portrait, level, HP/MP bars and stats
+    fn status_ui(&self) -> UiParts<'_> {
+        let mut ui = UiParts::default();
+        for (i, member) in self.party.iter().enumerate() {
+            let top = H as f32 - 8.0 - STATUS_PANEL_H * i as f32;
+            ui.windows
+                .push([8.0, top - STATUS_PANEL_H, W as f32 - 16.0, STATUS_PANEL_H]);
+            ui.sprites.push((
+                Transform {
+                    w: TILE_SZ as u16 * 2,
+                    h: TILE_SZ as u16 * 2,
+                    x: 32.0,
+                    y: top - STATUS_PANEL_H / 2.0,
+                    rot: 0.0,
+                },
+                // in front of the window
+                member.sprite.with_depth(0),
+            ));
+            let class = self
+                .classes
+                .get(&member.class)
+                .map_or(member.class.as_str(), |class| &class.name);
+            let next = self
+                .classes
+                .get(&member.class)
+                .map(|class| class.xp_for(member.level + 1))
+                .unwrap_or(member.xp);
+            let lines = [
+                format!("{:<6}Lv{:<3}{class}", member.name, member.level),
+                "HP".to_string(),
+                "MP".to_string(),
+            ];
+            let stats = member.stats();
+            let bars = [
+                (member.hp, stats.max_hp, HP_BAR),
+                (member.mp, stats.max_mp, MP_BAR),
+            ];
+            let stats = [
+                format!("ATK {:>3}", stats.attack),
+                format!("DEF {:>3}", stats.defense),
+                format!("SPD {:>3}", stats.speed),
+            ];
+            for (row, (line, stat)) in lines.into_iter().zip(stats).enumerate() {
+                let y = top - 12.0 - 12.0 * row as f32;
+                ui.texts.push((line, [56.0, y]));
+                ui.texts.push((stat, [240.0, y]));
+                if row == 0 {
+                    ui.texts
+                        .push((format!("XP {}/{next}", member.xp), [152.0, y]));
+                    continue;
+                }
+                // the filled part of the bar, then the empty part next to it
+                let (amount, max, fill) = bars[row - 1];
+                let filled = if max == 0 {
+                    0.0
+                } else {
+                    (BAR_W * amount.min(max) as f32 / max as f32).round()
+                };
+                for (x, w, uv) in [(0.0, filled, fill), (filled, BAR_W - filled, BAR_BACK)] {
+                    if w > 0.0 {
+                        ui.sprites.push((
+                            Transform {
+                                w: w as u16,
+                                h: 6,
+                                x: 76.0 + x + w / 2.0,
+                                y: y - 4.0,
+                                rot: 0.0,
+                            },
+                            uv,
+                        ));
+                    }
+                }
+                ui.texts
+                    .push((format!("{amount}/{max}"), [76.0 + BAR_W + 8.0, y]));
+            }
+        }
+        ui
+    }
+    // Draws a screen that's all UI, like the battle screen, in place of the map
+    fn render_ui(&self, frend: &mut Renderer, ui: UiParts) {
+        frend.sprite_group_resize(0, 0);
+        frend.sprite_group_resize(UI_GROUP, ui.sprite_count(&self.window));
+        let (sprite_posns, sprite_gfx) = frend.sprites_mut(UI_GROUP, ..);
+        let used = ui.draw(
+            &self.font,
+            &self.highlight_font,
+            &self.window,
+            0,
+            sprite_posns,
+            sprite_gfx,
+        );
+        sprite_posns[used..].fill(Transform::ZERO);
+        sprite_gfx[used..].fill(SheetRegion::ZERO);
     }
     fn render(&mut self, frend: &mut Renderer) {
-        // You could do `match self.game_mode { GameMode::Map => {...}, GameMode::Battle=> {...}}` in here
-
+        match &self.mode {
+            GameMode::Title(title) => return self.render_ui(frend, self.title_ui(title)),
+            GameMode::Battle(screen) => return self.render_ui(frend, self.battle_ui(screen)),
+            GameMode::Map => {}
+        }
         // make this exactly as big as we need
         frend.sprite_group_resize(0, self.sprite_count());
+        frend.sprite_group_set_camera(0, self.camera.camera2d());
 
-        let sprites_used = self.level().render_into(frend, 0);
+        let sprites_used =
+            self.level()
+                .render_into(frend, 0, self.visible_tiles(), self.tile_clock);
         let (sprite_posns, sprite_gfx) = frend.sprites_mut(0, sprites_used..);
 
-        for ((npc, _dlg), (trf, uv)) in self
+        for (npc, (trf, uv)) in self
             .npcs
             .iter()
             .zip(sprite_posns.iter_mut().zip(sprite_gfx.iter_mut()))
         {
-            *trf = Transform {
-                w: TILE_SZ as u16,
-                h: TILE_SZ as u16,
-                x: (npc.x * TILE_SZ as u16 + TILE_SZ as u16 / 2) as f32,
-                y: (H as u16 - npc.y * TILE_SZ as u16 - TILE_SZ as u16 / 2) as f32,
-                rot: 0.0,
-            };
+            *trf = self.tile_sprite(npc.pos);
             *uv = NPC;
         }
         let sprite_posns = &mut sprite_posns[self.npcs.len()..];
         let sprite_gfx = &mut sprite_gfx[self.npcs.len()..];
+        for (roamer, (trf, uv)) in self
+            .roamers
+            .iter()
+            .zip(sprite_posns.iter_mut().zip(sprite_gfx.iter_mut()))
+        {
+            *trf = self.tile_sprite(roamer.pos);
+            *uv = self.enemies.group_sprite(&roamer.group).unwrap_or(NPC);
+        }
+        let sprite_posns = &mut sprite_posns[self.roamers.len()..];
+        let sprite_gfx = &mut sprite_gfx[self.roamers.len()..];
         for ((_door_to, _door_to_pos, door_pos), (trf, uv)) in self
             .doors
             .iter()
             .zip(sprite_posns.iter_mut().zip(sprite_gfx.iter_mut()))
         {
-            *trf = Transform {
-                w: TILE_SZ as u16,
-                h: TILE_SZ as u16,
-                x: (door_pos.x * TILE_SZ as u16 + TILE_SZ as u16 / 2) as f32,
-                y: (H as u16 - door_pos.y * TILE_SZ as u16 - TILE_SZ as u16 / 2) as f32,
-                rot: 0.0,
-            };
+            *trf = self.tile_sprite(*door_pos);
             *uv = DOOR;
         }
         let sprite_posns = &mut sprite_posns[self.doors.len()..];
         let sprite_gfx = &mut sprite_gfx[self.doors.len()..];
-        sprite_posns[0] = Transform {
-            w: TILE_SZ as u16,
-            h: TILE_SZ as u16,
-            x: (self.player.x * TILE_SZ as u16 + TILE_SZ as u16 / 2) as f32,
-            y: (H as u16 - self.player.y * TILE_SZ as u16 - TILE_SZ as u16 / 2) as f32,
-            rot: 0.0,
-        };
-        sprite_gfx[0] = PLAYER;
-
-        let sprite_posns = &mut sprite_posns[1..];
-        let sprite_gfx = &mut sprite_gfx[1..];
-        // TODO: this should be extracted into a more general purpose function
-        // since we want to be able to draw text into boxes at a number of different places.
-        // This could turn into a call to like "draw_box_with_text_lines(...)" that returns how many sprites it uses up.
-        // Or you could make a Menu struct and populate it specially for a dialog.
-        if let Some(dlg) = self.active_dialog {
-            let dlg = &self.dialogs[dlg];
-            let mut used =
-                self.window
-                    .draw(sprite_posns, sprite_gfx, WIND_X, WIND_Y, WIND_W, WIND_H, 1);
-            let mut sprite_posns = &mut sprite_posns[used..];
-            let mut sprite_gfx = &mut sprite_gfx[used..];
-            let mut y = WIND_Y;
-            for line in dlg.split("\\n") {
-                self.font
-                    .draw_text(sprite_posns, sprite_gfx, line, [DLG_X, y], 0, 8.0);
-                used += line.len();
-                sprite_posns = &mut sprite_posns[line.len()..];
-                sprite_gfx = &mut sprite_gfx[line.len()..];
-                y -= 12.0; // line height plus a little extra
-            }
-        }
-    }
-    fn simulate(&mut self, input: &Input, _dt: f32) {
-        // TODO: in battle or menu mode, this should probably move a cursor around.
-        // You could consider something like "for each menu, if the menu is active, give it a chance to handle this input and if it does handle it then return from the function".
+        for (chest, (trf, uv)) in self
+            .chests
+            .iter()
+            .zip(sprite_posns.iter_mut().zip(sprite_gfx.iter_mut()))
+        {
+            *trf = self.tile_sprite(chest.pos);
+            *uv = if chest.opened { CHEST_OPEN } else { CHEST };
+        }
+        let sprite_posns = &mut sprite_posns[self.chests.len()..];
+        let sprite_gfx = &mut sprite_gfx[self.chests.len()..];
+        // followers go behind the player, in case they're bunched up on the same spot
+        for ((pos, member), (trf, uv)) in self
+            .trail
+            .spots()
+            .iter()
+            .zip(&self.party[1..])
+            .zip(sprite_posns.iter_mut().zip(sprite_gfx.iter_mut()))
+        {
+            *trf = self.tile_sprite(*pos);
+            *uv = member.sprite.with_depth(15);
+        }
+        let sprite_posns = &mut sprite_posns[self.trail.spots().len()..];
+        let sprite_gfx = &mut sprite_gfx[self.trail.spots().len()..];
+        sprite_posns[0] = self.tile_sprite(self.player);
+        sprite_gfx[0] = self.party[0].sprite;
 
+        frend.sprite_group_resize(UI_GROUP, self.ui_sprite_count());
+        let (sprite_posns, sprite_gfx) = frend.sprites_mut(UI_GROUP, ..);
+        // each layer goes in front of the ones below it
+        let layers = self.visible_layers();
+        let mut used = 0;
+        for (i, layer) in layers.iter().enumerate() {
+            let depth = (layers.len() - 1 - i) as u16 * UI_LAYER_DEPTH;
+            used += self.layer_ui(layer).draw(
+                &self.font,
+                &self.highlight_font,
+                &self.window,
+                depth,
+                &mut sprite_posns[used..],
+                &mut sprite_gfx[used..],
+            );
+        }
+        // sprite counts are estimates (e.g. \n takes up two characters but draws none), so clear any leftovers
+        sprite_posns[used..].fill(Transform::ZERO);
+        sprite_gfx[used..].fill(SheetRegion::ZERO);
+    }
+    fn simulate_battle(&mut self, input: &Input) {
+        let GameMode::Battle(screen) = &mut self.mode else {
+            return;
+        };
+        // the message from the last turn stays up until it's confirmed
+        if screen.message.is_some() {
+            if input.is_key_pressed(CONFIRM) {
+                screen.message = match screen.battle.outcome() {
+                    None => None,
+                    Some(_) if screen.over => {
+                        self.end_battle();
+                        return;
+                    }
+                    Some(outcome) => {
+                        screen.over = true;
+                        Some(match outcome {
+                            Outcome::Victory if screen.xp > 0 => {
+                                format!("You won! Got {} XP.", screen.xp)
+                            }
+                            Outcome::Victory => "You won!".to_string(),
+                            Outcome::Defeat => "You were defeated...".to_string(),
+                            Outcome::Fled => "You escaped.".to_string(),
+                        })
+                    }
+                };
+            }
+            return;
+        }
+        if screen.actor().is_none() {
+            let action = screen.battle.enemy_action();
+            screen.message = Some(screen.battle.act(action));
+            return;
+        }
+        let GameMode::Battle(screen) = &self.mode else {
+            return;
+        };
+        let Some(mut commands) = self.battle_commands(screen) else {
+            return;
+        };
+        let (event, cursor) = (commands.handle_input(input), commands.selected);
+        let GameMode::Battle(screen) = &mut self.mode else {
+            return;
+        };
+        screen.cursor = cursor;
+        let cursor = match event {
+            MenuEvent::Picked(cursor) => cursor,
+            MenuEvent::Cancelled if screen.menu != BattleMenu::Commands => {
+                screen.menu = BattleMenu::Commands;
+                screen.cursor = 0;
+                return;
+            }
+            _ => return,
+        };
+        screen.cursor = 0;
+        let action = match std::mem::replace(&mut screen.menu, BattleMenu::Commands) {
+            BattleMenu::Commands => {
+                screen.menu = match COMMANDS[cursor] {
+                    "Attack" => BattleMenu::Targets(Pending::Attack),
+                    "Skill" => BattleMenu::Skills,
+                    "Item" => BattleMenu::Items,
+                    "Defend" => {
+                        screen.message = Some(screen.battle.act(Action::Defend));
+                        return;
+                    }
+                    _ => {
+                        screen.message = Some(screen.battle.act(Action::Flee));
+                        return;
+                    }
+                };
+                return;
+            }
+            BattleMenu::Skills => {
+                let skill = screen.actor().map(|actor| actor.skills[cursor]).unwrap();
+                screen.menu = BattleMenu::Targets(Pending::Skill(skill));
+                return;
+            }
+            BattleMenu::Items => {
+                screen.menu = BattleMenu::Targets(Pending::Item(cursor));
+                return;
+            }
+            BattleMenu::Targets(pending) => {
+                let target = screen.targets(&pending)[cursor];
+                match pending {
+                    Pending::Attack => Action::Attack(target),
+                    Pending::Skill(skill) => Action::Skill(skill, target),
+                    Pending::Item(item) => {
+                        let (id, def, _) =
+                            BattleScreen::usable_items(&self.inventory, &self.items)[item];
+                        let action = Action::Item {
+                            name: def.name.clone(),
+                            effects: def.effects.clone(),
+                            target,
+                        };
+                        let id = id.to_string();
+                        self.inventory.remove(&id, 1);
+                        action
+                    }
+                }
+            }
+        };
+        screen.message = Some(screen.battle.act(action));
+    }
+    // Runs the pause menu, returning false once it's been closed
+    fn simulate_pause(&mut self, input: &Input, menu: &mut PauseMenu) -> bool {
+        match &mut menu.page {
+            PausePage::Main => {}
+            PausePage::Status => {
+                // the status page is just for looking at
+                if input.is_key_pressed(CONFIRM) || input.is_key_pressed(CANCEL) {
+                    menu.page = PausePage::Main;
+                }
+                return true;
+            }
+            PausePage::Equip(equip) => {
+                if !self.simulate_equip(input, equip) {
+                    menu.page = PausePage::Main;
+                }
+                return true;
+            }
+            PausePage::Save(slots) => {
+                let (event, cursor) = {
+                    let mut list = self.slot_list(slots, false);
+                    (list.handle_input(input), list.selected)
+                };
+                slots.cursor = cursor;
+                match event {
+                    MenuEvent::Cancelled => menu.page = PausePage::Main,
+                    MenuEvent::Picked(slot) => {
+                        let notice = match self.save_to(slot + 1) {
+                            Ok(()) => format!("Saved to slot {}.", slot + 1),
+                            Err(e) => format!("Couldn't save:\n{e}"),
+                        };
+                        self.ui.push(Layer::Notice(notice));
+                        menu.page = PausePage::Main;
+                    }
+                    _ => {}
+                }
+                return true;
+            }
+        }
+        if input.is_key_pressed(PAUSE) {
+            return false;
+        }
+        let (event, cursor) = {
+            let mut list = self.pause_list(menu);
+            (list.handle_input(input), list.selected)
+        };
+        menu.cursor = cursor;
+        match event {
+            MenuEvent::Cancelled => return false,
+            MenuEvent::Picked(picked) => match PAUSE_OPTIONS[picked] {
+                "Items" => self.ui.push(Layer::Inventory(InventoryMenu::default())),
+                "Status" => menu.page = PausePage::Status,
+                "Equip" => menu.page = PausePage::Equip(EquipMenu::default()),
+                "Save" => menu.page = PausePage::Save(self.slot_menu()),
+                _ => {}
+            },
+            _ => {}
+        }
+        true
+    }
+    // The title menu, then the list of saves to load from if Load gets picked
+    fn simulate_title(&mut self, input: &Input) {
+        let GameMode::Title(title) = &self.mode else {
+            return;
+        };
+        let (event, cursor) = {
+            let mut list = match &title.loading {
+                Some(slots) => self.slot_list(slots, true),
+                None => self.title_list(title),
+            };
+            (list.handle_input(input), list.selected)
+        };
+        let GameMode::Title(title) = &mut self.mode else {
+            return;
+        };
+        if event != MenuEvent::None {
+            title.message = None;
+        }
+        let MenuEvent::Picked(picked) = event else {
+            match &mut title.loading {
+                Some(_) if event == MenuEvent::Cancelled => title.loading = None,
+                Some(slots) => slots.cursor = cursor,
+                None => title.cursor = cursor,
+            }
+            return;
+        };
+        match &title.loading {
+            None if TITLE_OPTIONS[picked] == "New Game" => self.mode = GameMode::Map,
+            None => {
+                let slots = self.slot_menu();
+                if let GameMode::Title(title) = &mut self.mode {
+                    title.loading = Some(slots);
+                }
+            }
+            Some(slots) => {
+                // only slots with a save that parsed can be picked
+                let Some(Ok(save)) = slots.saves[picked].clone() else {
+                    return;
+                };
+                if let Err(e) = self.load(&save) {
+                    if let GameMode::Title(title) = &mut self.mode {
+                        title.message = Some(format!("Couldn't load slot {}: {e}", picked + 1));
+                    }
+                }
+            }
+        }
+    }
+    // Moves around the equip screen, returning false once it's been backed out of
+    fn simulate_equip(&mut self, input: &Input, menu: &mut EquipMenu) -> bool {
+        let slot = Slot::ALL[menu.slot];
+        let Some(choice) = menu.choice else {
+            let (event, selected) = {
+                let mut slots = self.equip_slots(menu);
+                (slots.handle_input(input), slots.selected)
+            };
+            menu.slot = selected;
+            let party = self.party.len();
+            if input.is_key_pressed(Key::ArrowLeft) {
+                menu.member = (menu.member + party - 1) % party;
+            } else if input.is_key_pressed(Key::ArrowRight) {
+                menu.member = (menu.member + 1) % party;
+            }
+            match event {
+                MenuEvent::Cancelled => return false,
+                MenuEvent::Picked(_) => menu.choice = Some(0),
+                _ => {}
+            }
+            return true;
+        };
+        let (event, selected) = {
+            let mut choices = self.equip_choice_list(menu).unwrap();
+            (choices.handle_input(input), choices.selected)
+        };
+        menu.choice = Some(selected);
+        match event {
+            MenuEvent::Cancelled => menu.choice = None,
+            MenuEvent::Picked(_) => {
+                // whatever comes off goes back in the inventory
+                let item = self.equip_choices(slot)[choice].clone();
+                if let Some(id) = &item {
+                    self.inventory.remove(id, 1);
+                }
+                let member = &mut self.party[menu.member];
+                if let Some(old) = member.equip(slot, item.as_deref(), &self.items) {
+                    self.inventory.add(&old, 1);
+                }
+                menu.choice = None;
+            }
+            _ => {}
+        }
+        true
+    }
+    // Runs the inventory menu, returning false once it's been closed
+    fn simulate_inventory(&mut self, input: &Input, menu: &mut InventoryMenu) -> bool {
+        // any key press clears a message
+        if menu.message.is_some() {
+            if input.is_key_pressed(CONFIRM) || input.is_key_pressed(CANCEL) {
+                menu.message = None;
+            }
+            return true;
+        }
+        // whichever box is innermost gets the keys
+        let (event, selected) = {
+            let mut list = self
+                .inventory_targets(menu)
+                .or_else(|| self.inventory_actions(menu))
+                .unwrap_or_else(|| self.inventory_list(menu));
+            (list.handle_input(input), list.selected)
+        };
+        match (menu.action, menu.target) {
+            (_, Some(_)) => menu.target = Some(selected),
+            (Some(_), None) => menu.action = Some(selected),
+            (None, None) => menu.cursor = selected,
+        }
+        let picked = match event {
+            MenuEvent::Picked(picked) => picked,
+            // cancel backs out of whichever box is innermost, and closes the menu from the list
+            MenuEvent::Cancelled => {
+                if menu.target.is_some() {
+                    menu.target = None;
+                } else if menu.action.is_some() {
+                    menu.action = None;
+                } else {
+                    return false;
+                }
+                return true;
+            }
+            // the key that opened the menu closes it again from the list
+            _ => return menu.action.is_some() || !input.is_key_pressed(INVENTORY),
+        };
+        let Some((id, _)) = self.inventory.stacks().get(menu.cursor).cloned() else {
+            return true;
+        };
+        let def = self.items.get(&id);
+        let name = def.map_or(id.clone(), |def| def.name.clone());
+        match (menu.action, menu.target) {
+            (_, Some(_)) => {
+                let effects = def.map(|def| def.effects.clone()).unwrap_or_default();
+                let member = &mut self.party[picked];
+                let mut fighter = member.combatant();
+                let result = fighter.apply_item(&effects);
+                member.sync(&fighter);
+                menu.message = Some(format!("{} uses {name}; {result}", member.name));
+                self.inventory.remove(&id, 1);
+                menu.action = None;
+                menu.target = None;
+            }
+            (Some(_), None) => match INV_ACTIONS[picked] {
+                "Use" if def.is_some_and(ItemDef::usable) => menu.target = Some(0),
+                "Use" => {
+                    menu.message = Some("You can't use that here.".to_string());
+                    menu.action = None;
+                }
+                _ if def.is_some_and(|def| def.kind == ItemKind::Key) => {
+                    menu.message = Some(format!("You can't let go of the {name}."));
+                    menu.action = None;
+                }
+                _ => {
+                    self.inventory.remove(&id, 1);
+                    menu.message = Some(format!("Dropped {name}."));
+                    menu.action = None;
+                }
+            },
+            (None, None) => menu.action = Some(0),
+        }
+        // the last of a stack may have just been used up
+        menu.cursor = menu.cursor.min(self.inventory.len().saturating_sub(1));
+        true
+    }
+    // Gives a layer the keys, returning whether it's still open afterwards
+    fn simulate_layer(&mut self, layer: &mut Layer, input: &Input, dt: f32) -> bool {
+        match layer {
+            Layer::Dialog(dbox) => self.simulate_dialog(input, dt, dbox),
+            Layer::Notice(_) => !(input.is_key_pressed(CONFIRM) || input.is_key_pressed(CANCEL)),
+            Layer::Inventory(menu) => self.simulate_inventory(input, menu),
+            Layer::Pause(menu) => self.simulate_pause(input, menu),
+        }
+    }
+    // While talking, text types itself out and confirm shows the rest of it right away.
+    // Once it's all there, the arrows pick a choice and confirm moves the conversation along.
+    fn simulate_dialog(&mut self, input: &Input, dt: f32, dbox: &mut DialogBox) -> bool {
+        dbox.typewriter.tick(dt);
+        let confirmed = input.is_key_pressed(CONFIRM);
+        if !dbox.typewriter.done() {
+            if confirmed {
+                dbox.typewriter.skip();
+            }
+            return true;
+        }
+        let parts = self.dialog_parts(dbox.convo);
+        if dbox.part + 1 < parts.len() {
+            if confirmed {
+                dbox.part += 1;
+                dbox.typewriter = parts[dbox.part].typewriter(TEXT_SPEED);
+            }
+            return true;
+        }
+        let convo = &mut dbox.convo;
+        if input.is_key_pressed(Key::ArrowUp) {
+            convo.move_cursor(&self.dialogs, &self.flags, -1);
+        } else if input.is_key_pressed(Key::ArrowDown) {
+            convo.move_cursor(&self.dialogs, &self.flags, 1);
+        }
+        if !confirmed {
+            return true;
+        }
+        match convo.confirm(&self.dialogs, &self.flags) {
+            Advance::Page => {
+                *dbox = self.dialog_box(dbox.convo);
+                true
+            }
+            // a battle cuts the conversation off once its node is done
+            Advance::Node(_) | Advance::End if self.pending_battle.is_some() => {
+                self.end_dialog();
+                false
+            }
+            Advance::Node(node) => {
+                self.apply_effects(node);
+                *dbox = self.dialog_box(dbox.convo);
+                true
+            }
+            Advance::End => {
+                self.end_dialog();
+                false
+            }
+        }
+    }
+    // Fights, talks to, or opens whatever's at pos, returning false if there's nothing there
+    fn interact(&mut self, pos: Vec2) -> bool {
+        if let Some(roamer) = self.roamers.iter().position(|r| r.pos == pos) {
+            self.fight_roamer(roamer);
+        } else if let Some(npc) = self.npcs.iter().position(|npc| npc.pos == pos) {
+            // open a dialog
+            let dlg = self.npcs[npc].dialog;
+            if self.dialogs.contains(dlg) {
+                self.start_dialog(dlg, npc);
+            }
+        } else if let Some(chest) = self.chests.iter().position(|c| c.pos == pos) {
+            self.open_chest(chest);
+        } else if self
+            .level()
+            .get_tile(pos)
+            .is_some_and(|tile| tile.flags.counter)
+        {
+            // reach across the counter to whoever's on the other side
+            let across = Vec2 {
+                x: (2 * pos.x as i32 - self.player.x as i32) as u16,
+                y: (2 * pos.y as i32 - self.player.y as i32) as u16,
+            };
+            return self.interact(across);
+        } else {
+            return false;
+        }
+        true
+    }
+    fn simulate(&mut self, input: &Input, dt: f32) {
+        match self.mode {
+            GameMode::Title(_) => return self.simulate_title(input),
+            GameMode::Battle(_) => return self.simulate_battle(input),
+            GameMode::Map => {}
+        }
+        self.tile_clock += dt;
+        let (target, bounds) = (self.level().tile_to_world(self.player), self.level_bounds());
+        self.camera.follow(target, bounds, dt);
         // use input to determine how to move your character
         // move enemies on their own
         // stop all characters from walking into solid tiles (try level.get_tile(pos))
         // etc
+        // the top layer gets the keys first
+        if let Some(mut top) = self.ui.pop() {
+            let below = self.ui.len();
+            if !self.simulate_layer(&mut top, input, dt) {
+                // whatever closed it used up the keys
+                return;
+            }
+            // anything it opened goes on top of it
+            self.ui.insert(below, top);
+        }
+        if self.ui.iter().any(Layer::blocks_map) {
+            return;
+        }
+        if input.is_key_pressed(INVENTORY) {
+            self.ui.push(Layer::Inventory(InventoryMenu::default()));
+            return;
+        }
+        if input.is_key_pressed(PAUSE) {
+            self.ui.push(Layer::Pause(PauseMenu::default()));
+            return;
+        }
+        self.move_roamers(dt);
+        if let GameMode::Battle(_) = self.mode {
+            return;
+        }
+        self.move_wait = (self.move_wait - dt).max(0.0);
         let dx = if input.is_key_pressed(Key::ArrowLeft) {
             -1
         } else if input.is_key_pressed(Key::ArrowRight) {
@@ -380,43 +2360,73 @@ impl Game {
         } else {
             0
         };
+        if (dx, dy) != (0, 0) {
+            self.facing = (dx, dy);
+        } else if input.is_key_pressed(CONFIRM) {
+            // interact with whatever's in front of the player without moving
+            let (fx, fy) = self.facing;
+            let ahead = Vec2 {
+                x: (self.player.x as i32 + fx) as u16,
+                y: (self.player.y as i32 + fy) as u16,
+            };
+            self.interact(ahead);
+            return;
+        }
         let dest = Vec2 {
             x: (self.player.x as i32 + dx) as u16,
             y: (self.player.y as i32 + dy) as u16,
         };
 
-        // dismiss dialog; this would have to change if you did yes/no in dialogs.
-        // you could also use a dedicated button here instead
-        if dx != 0 || dy != 0 {
-            self.active_dialog = None;
-        }
-
+        let here = self.level().get_tile(self.player).map(|tile| tile.flags);
+        let swim = self.inventory.count(WATER_ITEM) > 0;
         let moved = dest != self.player
-            && if let Some(TileData { solid: false, .. }) = self.level().get_tile(dest) {
-                if let Some((_npc, dlg)) = self.npcs.iter().find(|(p, _dlg)| *p == dest) {
-                    // open a dialog
-                    self.active_dialog = Some(*dlg);
+            && self.move_wait == 0.0
+            && here.unwrap_or_default().can_leave((dx, dy))
+            && match self.level().get_tile(dest) {
+                Some(tile) if tile.flags.can_enter((dx, dy), swim) => {
+                    if self.interact(dest) {
+                        false
+                    } else {
+                        self.trail.follow(self.player, self.party.len() - 1);
+                        self.player = dest;
+                        true
+                    }
+                }
+                Some(tile) if tile.flags.counter => {
+                    self.interact(dest);
                     false
-                } else {
-                    self.player = dest;
-                    true
                 }
-            } else {
-                false
+                _ => false,
             };
         if moved {
+            let flags = self.level().get_tile(self.player).unwrap().flags;
+            if flags.slow {
+                self.move_wait = SLOW_STEP;
+            }
+            if flags.damage > 0 {
+                for member in self.party.iter_mut() {
+                    member.hurt(flags.damage);
+                }
+            }
             for (door_to, door_to_pos, door_pos) in self.doors.iter() {
                 if *door_pos == self.player {
-                    let dest = self
-                        .levels
-                        .iter()
-                        .position(|l| l.name() == door_to)
-                        .expect("door to invalid room {door_to}!");
-                    self.current_level = dest;
+                    assert!(
+                        self.levels.contains_key(door_to),
+                        "door to invalid room {door_to}!"
+                    );
+                    self.current_level = door_to.clone();
                     self.enter_level(*door_to_pos);
-                    break;
+                    return;
                 }
             }
+            if flags.no_encounter {
+                return;
+            }
+            let zones = self.levels[&self.current_level].encounters();
+            if let Some(group) = roll_encounter(zones, self.player, &mut self.rng) {
+                let group = group.to_string();
+                self.start_battle(&group);
+            }
         }
     }
 }
@@ -0,0 +1,437 @@
+use crate::dialog::Condition;
+use crate::inventory::Inventory;
+use crate::party::Member;
+use crate::text::{paginate, reveal, wrap_indices, TextMetrics, Typewriter};
+use std::collections::HashSet;
+
+/*
+Dialog pages can have a little markup in them:
+
+{player_name}          the name of whoever's leading the party
+{party N}              the name of party member N (1 is the leader), or nothing
+{count ITEM}           how many of ITEM are in the inventory
+{if FLAG TEXT}         TEXT, but only when FLAG is set (or clear, for `if !FLAG`)
+{pause} {pause SECS}   the text stops typing out for a moment (half a second by default)
+*TEXT*                 TEXT is highlighted
+{{ **                  a plain { or *
+
+Pages are parsed when they're shown rather than when the dialog file is loaded, so a
+mistake in the markup just shows the page as it's written; the content linter reports
+those mistakes, along with variables that aren't any of the above.
+*/
+
+const DEFAULT_PAUSE: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Var {
+    PlayerName,
+    // 1-based
+    Party(usize),
+    Count(String),
+    If(Condition, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Markup {
+    Text(String),
+    Var(Var),
+    // seconds
+    Pause(f32),
+    // turns highlighting on or off
+    Highlight,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkupError {
+    Unclosed { col: usize },
+    UnclosedHighlight { col: usize },
+    UnknownVar { col: usize, name: String },
+    BadVar { col: usize, usage: &'static str },
+}
+
+impl MarkupError {
+    pub fn col(&self) -> usize {
+        match self {
+            Self::Unclosed { col }
+            | Self::UnclosedHighlight { col }
+            | Self::UnknownVar { col, .. }
+            | Self::BadVar { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::Unclosed { .. } => "this { is never closed".to_string(),
+            Self::UnclosedHighlight { .. } => "this * highlight is never closed".to_string(),
+            Self::UnknownVar { name, .. } => format!("unknown variable {name}"),
+            Self::BadVar { usage, .. } => format!("expected {{{usage}}}"),
+        }
+    }
+}
+
+impl std::fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.col(), self.message())
+    }
+}
+
+// What's inside a {}, starting at column col
+fn parse_var(inside: &str, col: usize) -> Result<Markup, MarkupError> {
+    let (name, args) = inside.trim().split_once(' ').unwrap_or((inside.trim(), ""));
+    let args = args.trim();
+    let bad = |usage| Err(MarkupError::BadVar { col, usage });
+    match name {
+        "player_name" if args.is_empty() => Ok(Markup::Var(Var::PlayerName)),
+        "player_name" => bad("player_name"),
+        "party" => match args.parse() {
+            Ok(n) if n > 0 => Ok(Markup::Var(Var::Party(n))),
+            _ => bad("party N"),
+        },
+        "count" if !args.is_empty() && !args.contains(' ') => {
+            Ok(Markup::Var(Var::Count(args.to_string())))
+        }
+        "count" => bad("count ITEM"),
+        "if" => match args.split_once(' ') {
+            Some((flag, text)) if !flag.is_empty() && flag != "!" => Ok(Markup::Var(Var::If(
+                Condition::parse(flag),
+                text.trim_start().to_string(),
+            ))),
+            _ => bad("if FLAG TEXT"),
+        },
+        "pause" if args.is_empty() => Ok(Markup::Pause(DEFAULT_PAUSE)),
+        "pause" => match args.parse::<f32>() {
+            Ok(secs) if secs >= 0.0 => Ok(Markup::Pause(secs)),
+            _ => bad("pause SECS"),
+        },
+        _ => Err(MarkupError::UnknownVar {
+            col,
+            name: name.to_string(),
+        }),
+    }
+}
+
+/// Breaks a page up into plain text and markup
+pub fn parse(text: &str) -> Result<Vec<Markup>, MarkupError> {
+    let mut parsed = vec![];
+    let mut plain = String::new();
+    // column of the * that started the highlight we're in, if any
+    let mut highlight = None;
+    let mut chars = text.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        let col = i + 1;
+        if !matches!(c, '{' | '*') {
+            plain.push(c);
+            continue;
+        }
+        if chars.next_if(|&(_, next)| next == c).is_some() {
+            plain.push(c);
+            continue;
+        }
+        if !plain.is_empty() {
+            parsed.push(Markup::Text(std::mem::take(&mut plain)));
+        }
+        if c == '*' {
+            highlight = match highlight {
+                Some(_) => None,
+                None => Some(col),
+            };
+            parsed.push(Markup::Highlight);
+            continue;
+        }
+        let mut inside = String::new();
+        loop {
+            match chars.next() {
+                Some((_, '}')) => break,
+                Some((_, c)) => inside.push(c),
+                None => return Err(MarkupError::Unclosed { col }),
+            }
+        }
+        parsed.push(parse_var(&inside, col)?);
+    }
+    if let Some(col) = highlight {
+        return Err(MarkupError::UnclosedHighlight { col });
+    }
+    if !plain.is_empty() {
+        parsed.push(Markup::Text(plain));
+    }
+    Ok(parsed)
+}
+
+/// The game state variables are filled in from
+pub struct Context<'a> {
+    pub party: &'a [Member],
+    pub inventory: &'a Inventory,
+    pub flags: &'a HashSet<String>,
+}
+
+/// Text with its markup worked out
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Marked {
+    pub text: String,
+    // whether each character of the text is highlighted
+    pub highlights: Vec<bool>,
+    // (index of a character, seconds to wait before it shows up), in order
+    pub pauses: Vec<(usize, f32)>,
+}
+
+/// A boxful of marked text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Part {
+    pub lines: Vec<String>,
+    pub highlights: Vec<Vec<bool>>,
+    // (how many characters into the part, seconds to wait there)
+    pub pauses: Vec<(usize, f32)>,
+}
+
+impl Marked {
+    /// Text without any markup in it
+    pub fn plain(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            highlights: vec![false; text.chars().count()],
+            pauses: vec![],
+        }
+    }
+    /// Fills in the variables in some parsed text
+    pub fn render(markup: &[Markup], context: &Context) -> Self {
+        let mut marked = Self::default();
+        let mut highlight = false;
+        for piece in markup {
+            let text = match piece {
+                Markup::Text(text) => text.clone(),
+                Markup::Var(Var::PlayerName) => context
+                    .party
+                    .first()
+                    .map_or(String::new(), |m| m.name.clone()),
+                Markup::Var(Var::Party(n)) => context
+                    .party
+                    .get(n - 1)
+                    .map_or(String::new(), |m| m.name.clone()),
+                Markup::Var(Var::Count(item)) => context.inventory.count(item).to_string(),
+                Markup::Var(Var::If(condition, text)) if condition.holds(context.flags) => {
+                    text.clone()
+                }
+                Markup::Var(Var::If(..)) => String::new(),
+                Markup::Pause(secs) => {
+                    marked.pauses.push((marked.highlights.len(), *secs));
+                    continue;
+                }
+                Markup::Highlight => {
+                    highlight = !highlight;
+                    continue;
+                }
+            };
+            marked
+                .highlights
+                .extend(std::iter::repeat_n(highlight, text.chars().count()));
+            marked.text.push_str(&text);
+        }
+        marked
+    }
+    /// Wraps the text to `width` and splits it into boxfuls of `per_page` lines
+    pub fn layout(&self, width: f32, metrics: TextMetrics, per_page: usize) -> Vec<Part> {
+        let chars: Vec<char> = self.text.chars().collect();
+        let pages = paginate(&wrap_indices(&self.text, width, metrics), per_page);
+        let mut parts: Vec<Part> = pages
+            .iter()
+            .map(|lines| Part {
+                lines: lines
+                    .iter()
+                    .map(|line| line.iter().map(|&i| chars[i]).collect())
+                    .collect(),
+                highlights: lines
+                    .iter()
+                    .map(|line| line.iter().map(|&i| self.highlights[i]).collect())
+                    .collect(),
+                pauses: vec![],
+            })
+            .collect();
+        for &(at, secs) in self.pauses.iter() {
+            // a pause goes before the first character that's shown at or after it
+            let found = pages.iter().enumerate().find_map(|(part, lines)| {
+                let pos = lines.iter().flatten().position(|&i| i >= at)?;
+                Some((part, pos))
+            });
+            if let Some((part, pos)) = found {
+                parts[part].pauses.push((pos, secs));
+            }
+        }
+        parts
+    }
+}
+
+impl Part {
+    /// How many characters there are to type out
+    pub fn len(&self) -> usize {
+        self.lines.iter().map(|line| line.chars().count()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The first `count` characters, line by line, in runs that are either all
+    /// highlighted or all not
+    pub fn runs(&self, count: usize) -> Vec<Vec<(String, bool)>> {
+        reveal(&self.lines, count)
+            .iter()
+            .zip(self.highlights.iter())
+            .map(|(line, highlights)| {
+                let mut runs: Vec<(String, bool)> = vec![];
+                for (c, &highlight) in line.chars().zip(highlights.iter()) {
+                    match runs.last_mut() {
+                        Some((run, lit)) if *lit == highlight => run.push(c),
+                        _ => runs.push((c.to_string(), highlight)),
+                    }
+                }
+                runs
+            })
+            .collect()
+    }
+    /// Something to type this part out with
+    pub fn typewriter(&self, speed: Option<f32>) -> Typewriter {
+        Typewriter::with_pauses(self.len(), speed, self.pauses.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle::Stats;
+    use crate::classes::ClassDef;
+    use frenderer::sprites::SheetRegion;
+
+    fn member(name: &str) -> Member {
+        let class = ClassDef {
+            name: "Hero".to_string(),
+            base: Stats::default(),
+            growth: Stats::default(),
+            xp: 10,
+            skills: vec![],
+            sprite: SheetRegion::ZERO,
+        };
+        Member::new(name, "hero", &class)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("hi {player_name}, *look*{pause 1.5}!").unwrap(),
+            vec![
+                Markup::Text("hi ".to_string()),
+                Markup::Var(Var::PlayerName),
+                Markup::Text(", ".to_string()),
+                Markup::Highlight,
+                Markup::Text("look".to_string()),
+                Markup::Highlight,
+                Markup::Pause(1.5),
+                Markup::Text("!".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse("{if !met nice to meet you}{{**").unwrap(),
+            vec![
+                Markup::Var(Var::If(
+                    Condition::parse("!met"),
+                    "nice to meet you".to_string()
+                )),
+                Markup::Text("{*".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse("{pause}").unwrap(),
+            vec![Markup::Pause(DEFAULT_PAUSE)]
+        );
+        assert_eq!(parse("").unwrap(), vec![]);
+    }
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("you have {nope} of those").unwrap_err(),
+            MarkupError::UnknownVar {
+                col: 10,
+                name: "nope".to_string()
+            }
+        );
+        assert_eq!(
+            parse("ab {party x}").unwrap_err().to_string(),
+            "4: expected {party N}"
+        );
+        assert_eq!(
+            parse("{count}").unwrap_err().to_string(),
+            "1: expected {count ITEM}"
+        );
+        assert_eq!(
+            parse("{if met}").unwrap_err().to_string(),
+            "1: expected {if FLAG TEXT}"
+        );
+        assert_eq!(
+            parse("oh {player_name").unwrap_err(),
+            MarkupError::Unclosed { col: 4 }
+        );
+        assert_eq!(
+            parse("a *b* *c").unwrap_err(),
+            MarkupError::UnclosedHighlight { col: 7 }
+        );
+    }
+    #[test]
+    fn test_render() {
+        let party = [member("Ada"), member("Bram")];
+        let mut inventory = Inventory::default();
+        inventory.add("potion", 3);
+        let flags: HashSet<String> = ["met".to_string()].into();
+        let context = Context {
+            party: &party,
+            inventory: &inventory,
+            flags: &flags,
+        };
+        let markup =
+            parse("{player_name} and {party 2}{party 3}: *{count potion}*{pause} potions{if met !}{if !met ?}")
+                .unwrap();
+        let marked = Marked::render(&markup, &context);
+        assert_eq!(marked.text, "Ada and Bram: 3 potions!");
+        assert_eq!(
+            marked
+                .highlights
+                .iter()
+                .enumerate()
+                .filter(|(_, &h)| h)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>(),
+            vec![14]
+        );
+        assert_eq!(marked.pauses, vec![(15, DEFAULT_PAUSE)]);
+        assert_eq!(Marked::plain("a*b").highlights, vec![false; 3]);
+    }
+    #[test]
+    fn test_layout() {
+        let metrics = TextMetrics {
+            advance: 8.0,
+            space: 16.0,
+        };
+        let marked = Marked {
+            text: "one two three four".to_string(),
+            highlights: "one two three four".chars().map(|c| c == 't').collect(),
+            pauses: vec![(4, 1.0), (8, 2.0), (13, 3.0)],
+        };
+        // "one two" is 64 pixels wide, so each line has two words at most
+        let parts = marked.layout(64.0, metrics, 1);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].lines, vec!["one two"]);
+        assert!(parts[0].highlights[0][4]);
+        assert_eq!(parts[0].len(), 7);
+        assert_eq!(parts[0].pauses, vec![(4, 1.0)]);
+        // the space a line was broken at isn't shown, so its pause goes to the next word
+        assert_eq!(parts[1].lines, vec!["three"]);
+        assert_eq!(parts[1].pauses, vec![(0, 2.0)]);
+        assert_eq!(parts[2].pauses, vec![(0, 3.0)]);
+        assert_eq!(
+            parts[0].runs(6),
+            vec![vec![
+                ("one ".to_string(), false),
+                ("t".to_string(), true),
+                ("w".to_string(), false)
+            ]]
+        );
+        let mut typewriter = parts[0].typewriter(Some(10.0));
+        typewriter.tick(1.0);
+        assert_eq!(typewriter.shown(), 4);
+    }
+}
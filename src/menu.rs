@@ -0,0 +1,311 @@
+use frenderer::bitfont::BitFont;
+use frenderer::input::{Input, Key};
+use frenderer::nineslice::NineSlice;
+use frenderer::sprites::{SheetRegion, Transform};
+
+/*
+A Menu is a window with an optional title and a list of options, one of which is
+selected.  It's used for anything the player picks from a list of: battle commands,
+the inventory, the pause menu, dialog choices and so on.
+
+Menus are cheap to make, so screens keep track of their own cursor and build a fresh
+Menu from it whenever they need to draw or handle input, then read `selected` back out
+afterwards.  The arrow keys move the selection (wrapping around at either end), the list
+scrolls to keep it in view, and options that are disabled can be selected but not picked.
+*/
+
+// Keys for picking and backing out of things; the arrow keys move the cursor
+pub const CONFIRM: Key = Key::KeyZ;
+pub const CANCEL: Key = Key::KeyX;
+
+/// Distance from one line of text to the next
+pub const LINE_H: f32 = 12.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuOption {
+    pub label: String,
+    pub enabled: bool,
+}
+
+/// What happened to a menu on a key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuEvent {
+    None,
+    // the selection changed
+    Moved,
+    // an enabled option was confirmed
+    Picked(usize),
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct Menu<'a> {
+    // x, y of the bottom left corner, then width and height
+    pub rect: [f32; 4],
+    pub title: Option<String>,
+    pub options: Vec<MenuOption>,
+    pub selected: usize,
+    // index of the first option in view
+    pub scroll: usize,
+    // text goes at this depth and the window just behind it
+    pub depth: u16,
+    pub window: &'a NineSlice,
+    pub font: &'a BitFont,
+}
+
+impl<'a> Menu<'a> {
+    pub fn new(rect: [f32; 4], window: &'a NineSlice, font: &'a BitFont) -> Self {
+        Self {
+            rect,
+            title: None,
+            options: vec![],
+            selected: 0,
+            scroll: 0,
+            depth: 0,
+            window,
+            font,
+        }
+    }
+    /// How tall a window has to be to show this many lines of text
+    pub fn height_for(lines: usize) -> f32 {
+        32.0 + LINE_H * lines as f32
+    }
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+    /// Labels, and whether each one can be picked
+    pub fn with_options<S: Into<String>>(
+        mut self,
+        options: impl IntoIterator<Item = (S, bool)>,
+    ) -> Self {
+        self.options = options
+            .into_iter()
+            .map(|(label, enabled)| MenuOption {
+                label: label.into(),
+                enabled,
+            })
+            .collect();
+        self.select(self.selected);
+        self
+    }
+    pub fn with_selected(mut self, selected: usize) -> Self {
+        self.select(selected);
+        self
+    }
+    pub fn with_depth(mut self, depth: u16) -> Self {
+        self.depth = depth;
+        self
+    }
+    /// Selects an option (or the last one, if there aren't that many) and scrolls it into view
+    pub fn select(&mut self, selected: usize) {
+        self.selected = selected.min(self.options.len().saturating_sub(1));
+        let rows = self.rows().max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+    }
+    /// How many options fit in the window at once
+    pub fn rows(&self) -> usize {
+        let lines = ((self.rect[3] - 28.0) / LINE_H).max(0.0) as usize;
+        lines.saturating_sub(usize::from(self.title.is_some()))
+    }
+    /// Where the text on a line starts; line 0 is the title, if there is one
+    fn line_pos(&self, line: usize, indent: f32) -> [f32; 2] {
+        let [x, y, _, h] = self.rect;
+        [x + indent, y + h - 16.0 - LINE_H * line as f32]
+    }
+    // the options in view, with their indices
+    fn visible(&self) -> impl Iterator<Item = (usize, &MenuOption)> {
+        self.options
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(self.rows())
+    }
+    fn label(option: &MenuOption) -> String {
+        // there's no dimmed text, so mark options that can't be picked instead
+        if option.enabled {
+            option.label.clone()
+        } else {
+            format!("-{}", option.label)
+        }
+    }
+    // arrows for when there are more options above or below what's in view
+    fn more(&self) -> (bool, bool) {
+        (
+            self.scroll > 0,
+            self.options.len() > self.scroll + self.rows(),
+        )
+    }
+    pub fn sprite_count(&self) -> usize {
+        let [_, _, w, h] = self.rect;
+        let (above, below) = self.more();
+        self.window.sprite_count(w, h)
+            + self.title.as_ref().map_or(0, String::len)
+            + self
+                .visible()
+                .map(|(_, option)| Self::label(option).len())
+                .sum::<usize>()
+            + usize::from(!self.options.is_empty())
+            + usize::from(above)
+            + usize::from(below)
+    }
+    /// Draws the window and everything in it, returning how many sprites it used
+    pub fn draw_into(&self, trfs: &mut [Transform], uvs: &mut [SheetRegion]) -> usize {
+        let [x, y, w, h] = self.rect;
+        let mut used = self.window.draw(trfs, uvs, x, y, w, h, self.depth + 1);
+        let mut text = |text: &str, pos: [f32; 2]| {
+            let (_, n) = self.font.draw_text(
+                &mut trfs[used..],
+                &mut uvs[used..],
+                text,
+                pos,
+                self.depth,
+                8.0,
+            );
+            used += n;
+        };
+        let first_line = usize::from(self.title.is_some());
+        if let Some(title) = &self.title {
+            text(title, self.line_pos(0, 16.0));
+        }
+        for (row, (i, option)) in self.visible().enumerate() {
+            if i == self.selected {
+                text(">", self.line_pos(first_line + row, 8.0));
+            }
+            text(&Self::label(option), self.line_pos(first_line + row, 16.0));
+        }
+        let (above, below) = self.more();
+        if above {
+            text("^", self.line_pos(first_line, w - 24.0));
+        }
+        if below {
+            text("v", [x + w - 24.0, y + 16.0]);
+        }
+        used
+    }
+    /// Reacts to whichever of the menu's keys was just pressed
+    pub fn handle_input(&mut self, input: &Input) -> MenuEvent {
+        [CANCEL, CONFIRM, Key::ArrowUp, Key::ArrowDown]
+            .into_iter()
+            .find(|key| input.is_key_pressed(*key))
+            .map_or(MenuEvent::None, |key| self.press(key))
+    }
+    /// Reacts to a key press
+    pub fn press(&mut self, key: Key) -> MenuEvent {
+        let count = self.options.len();
+        match key {
+            CANCEL => MenuEvent::Cancelled,
+            CONFIRM => match self.options.get(self.selected) {
+                Some(option) if option.enabled => MenuEvent::Picked(self.selected),
+                _ => MenuEvent::None,
+            },
+            Key::ArrowUp | Key::ArrowDown if count > 1 => {
+                let delta = if key == Key::ArrowUp { count - 1 } else { 1 };
+                self.select((self.selected + delta) % count);
+                MenuEvent::Moved
+            }
+            _ => MenuEvent::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frenderer::nineslice::{CornerSlice, Repeat, Slice};
+
+    fn window() -> NineSlice {
+        let slice = Slice {
+            w: 16.0,
+            h: 16.0,
+            region: SheetRegion::ZERO,
+            repeat: Repeat::Stretch,
+        };
+        NineSlice::with_corner_edge_center(
+            CornerSlice {
+                w: 16.0,
+                h: 16.0,
+                region: SheetRegion::ZERO,
+            },
+            slice,
+            slice,
+            slice,
+        )
+    }
+    fn font() -> BitFont {
+        BitFont::with_sheet_region(' '..='~', SheetRegion::rect(0, 738, 288, 765), 8, 8, 1, 1)
+    }
+    fn options(n: usize) -> Vec<(String, bool)> {
+        (0..n).map(|i| (format!("Option {i}"), i != 1)).collect()
+    }
+
+    #[test]
+    fn test_rows() {
+        let (window, font) = (window(), font());
+        let menu = Menu::new([0.0, 0.0, 100.0, Menu::height_for(5)], &window, &font);
+        assert_eq!(menu.rows(), 5);
+        assert_eq!(menu.with_title("Items").rows(), 4);
+        let tiny = Menu::new([0.0, 0.0, 100.0, 20.0], &window, &font);
+        assert_eq!(tiny.rows(), 0);
+    }
+    #[test]
+    fn test_press() {
+        let (window, font) = (window(), font());
+        let mut menu = Menu::new([0.0, 0.0, 100.0, Menu::height_for(3)], &window, &font)
+            .with_options(options(5));
+        assert_eq!(menu.press(CONFIRM), MenuEvent::Picked(0));
+        assert_eq!(menu.press(Key::ArrowDown), MenuEvent::Moved);
+        // disabled options can be landed on but not picked
+        assert_eq!(menu.press(CONFIRM), MenuEvent::None);
+        assert_eq!(menu.press(CANCEL), MenuEvent::Cancelled);
+        menu.press(Key::ArrowDown);
+        menu.press(Key::ArrowDown);
+        assert_eq!((menu.selected, menu.scroll), (3, 1));
+        // going up from the top wraps around to the bottom
+        menu.select(0);
+        assert_eq!(menu.scroll, 0);
+        menu.press(Key::ArrowUp);
+        assert_eq!((menu.selected, menu.scroll), (4, 2));
+        assert_eq!(menu.press(Key::ArrowDown), MenuEvent::Moved);
+        assert_eq!((menu.selected, menu.scroll), (0, 0));
+        // nothing to move between
+        let mut empty = Menu::new([0.0, 0.0, 100.0, 60.0], &window, &font);
+        assert_eq!(empty.press(Key::ArrowDown), MenuEvent::None);
+        assert_eq!(empty.press(CONFIRM), MenuEvent::None);
+    }
+    #[test]
+    fn test_selected_is_clamped() {
+        let (window, font) = (window(), font());
+        let menu = Menu::new([0.0, 0.0, 100.0, Menu::height_for(2)], &window, &font)
+            .with_options(options(4))
+            .with_selected(9);
+        assert_eq!((menu.selected, menu.scroll), (3, 2));
+        // the selection sticks when the options are replaced with fewer
+        let menu = menu.with_options(options(2));
+        assert_eq!((menu.selected, menu.scroll), (1, 1));
+    }
+    #[test]
+    fn test_draw() {
+        let (window, font) = (window(), font());
+        let menu = Menu::new([0.0, 0.0, 100.0, Menu::height_for(3)], &window, &font)
+            .with_title("Pick")
+            .with_options(options(4))
+            .with_selected(2)
+            .with_depth(3);
+        assert_eq!(menu.scroll, 1);
+        // window, title, cursor, two rows ("-" for the disabled one), and both arrows
+        let count = menu.sprite_count();
+        assert_eq!(count, window.sprite_count(100.0, 68.0) + 4 + 1 + 9 + 8 + 2);
+        let mut trfs = vec![Transform::ZERO; count];
+        let mut uvs = vec![SheetRegion::ZERO; count];
+        assert_eq!(menu.draw_into(&mut trfs, &mut uvs), count);
+        // the text is in front of the window
+        let window_sprites = window.sprite_count(100.0, 68.0);
+        assert!(uvs[..window_sprites].iter().all(|uv| uv.depth == 4));
+        assert!(uvs[window_sprites..].iter().all(|uv| uv.depth == 3));
+    }
+}
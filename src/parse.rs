@@ -0,0 +1,137 @@
+//! Shared helpers for the line-oriented, whitespace-separated text formats
+//! in `content/` (levels, the world manifest, and so on).
+use std::str::FromStr;
+
+/// The problems any line-oriented format can run into; each format's own
+/// error type converts from this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldError {
+    MissingField {
+        line: usize,
+        col: usize,
+        field: &'static str,
+    },
+    BadNumber {
+        line: usize,
+        col: usize,
+        field: &'static str,
+        text: String,
+    },
+    TrailingContent {
+        line: usize,
+        col: usize,
+    },
+}
+
+impl FieldError {
+    pub fn line(&self) -> usize {
+        match self {
+            Self::MissingField { line, .. }
+            | Self::BadNumber { line, .. }
+            | Self::TrailingContent { line, .. } => *line,
+        }
+    }
+    pub fn col(&self) -> usize {
+        match self {
+            Self::MissingField { col, .. }
+            | Self::BadNumber { col, .. }
+            | Self::TrailingContent { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::MissingField { field, .. } => format!("expected {field}"),
+            Self::BadNumber { field, text, .. } => {
+                format!("couldn't parse {field} from {text:?}")
+            }
+            Self::TrailingContent { .. } => "unexpected trailing content".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// Whitespace-separated chunks of one line, remembering where each one started.
+pub(crate) struct Fields<'a> {
+    line_no: usize,
+    line: &'a str,
+    chunks: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Fields<'a> {
+    pub(crate) fn new(line_no: usize, line: &'a str) -> Self {
+        Self {
+            line_no,
+            line,
+            chunks: line.split_whitespace(),
+        }
+    }
+    fn col_of(&self, chunk: &str) -> usize {
+        let byte_offset = chunk.as_ptr() as usize - self.line.as_ptr() as usize;
+        self.line[..byte_offset].chars().count() + 1
+    }
+    fn end_col(&self) -> usize {
+        self.line.trim_end().chars().count() + 1
+    }
+    pub(crate) fn chunk(&mut self, field: &'static str) -> Result<(usize, &'a str), FieldError> {
+        self.chunks
+            .next()
+            .map(|chunk| (self.col_of(chunk), chunk))
+            .ok_or(FieldError::MissingField {
+                line: self.line_no,
+                col: self.end_col(),
+                field,
+            })
+    }
+    pub(crate) fn peek(&self) -> Option<(usize, &'a str)> {
+        let chunk = self.chunks.clone().next()?;
+        Some((self.col_of(chunk), chunk))
+    }
+    pub(crate) fn num<T: FromStr>(&mut self, field: &'static str) -> Result<T, FieldError> {
+        let (col, chunk) = self.chunk(field)?;
+        T::from_str(chunk).map_err(|_| FieldError::BadNumber {
+            line: self.line_no,
+            col,
+            field,
+            text: chunk.to_string(),
+        })
+    }
+    /// Everything left on the line, for free text like names and dialog; errors if it's empty.
+    pub(crate) fn rest(&mut self, field: &'static str) -> Result<(usize, &'a str), FieldError> {
+        let (col, first) = self.chunk(field)?;
+        let start = first.as_ptr() as usize - self.line.as_ptr() as usize;
+        self.chunks = "".split_whitespace();
+        Ok((col, self.line[start..].trim_end()))
+    }
+    pub(crate) fn trailing(&mut self) -> FieldError {
+        let col = self
+            .chunks
+            .next()
+            .map(|chunk| self.col_of(chunk))
+            .unwrap_or(1);
+        FieldError::TrailingContent {
+            line: self.line_no,
+            col,
+        }
+    }
+    pub(crate) fn finish(mut self) -> Result<(), FieldError> {
+        match self.chunks.clone().next() {
+            None => Ok(()),
+            Some(_) => Err(self.trailing()),
+        }
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = (usize, &'a str);
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunks.next()?;
+        Some((self.col_of(chunk), chunk))
+    }
+}
@@ -0,0 +1,349 @@
+use crate::battle::{derived_stats, Combatant, Side, Stats};
+use crate::classes::ClassDef;
+use crate::geom::Vec2;
+use crate::items::{Items, Slot};
+use frenderer::sprites::SheetRegion;
+
+/*
+A party member outside of battle: who they are, how far along they've grown, what
+they're wearing, and how worn out they are.  Their base stats always match their class
+at their level, and their equipment adds to those the same way it does in battle.
+They turn into a Combatant for the length of each battle.
+
+On the map the first member leads and everyone else follows along behind them in a
+line, each one standing where the member ahead of them was a step ago.
+*/
+
+/// Nobody else can join once the party is this big
+pub const MAX_PARTY: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub class: String,
+    pub level: u32,
+    // total XP earned so far
+    pub xp: u32,
+    pub base: Stats,
+    // item ids of what's worn in each slot, indexed by Slot
+    pub equipment: [Option<String>; 3],
+    // what the equipment adds up to
+    pub gear: Stats,
+    pub hp: u32,
+    pub mp: u32,
+    pub skills: Vec<usize>,
+    pub sprite: SheetRegion,
+}
+
+/// What changed when someone went up one or more levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelUp {
+    pub level: u32,
+    pub gains: Stats,
+}
+
+impl LevelUp {
+    /// The stats that went up, like "HP +3 ATK +1"
+    pub fn describe(&self) -> String {
+        let gains = self.gains;
+        let gains: Vec<String> = [
+            ("HP", gains.max_hp),
+            ("MP", gains.max_mp),
+            ("ATK", gains.attack),
+            ("DEF", gains.defense),
+            ("SPD", gains.speed),
+        ]
+        .iter()
+        .filter(|(_, gain)| *gain > 0)
+        .map(|(stat, gain)| format!("{stat} +{gain}"))
+        .collect();
+        gains.join(" ")
+    }
+}
+
+impl Member {
+    /// A level 1 member of the given class at full HP and MP
+    pub fn new(name: &str, class_id: &str, class: &ClassDef) -> Self {
+        let stats = class.stats_at(1);
+        Self {
+            name: name.to_string(),
+            class: class_id.to_string(),
+            level: 1,
+            xp: 0,
+            base: stats,
+            equipment: Default::default(),
+            gear: Stats::default(),
+            hp: stats.max_hp,
+            mp: stats.max_mp,
+            skills: class.skills.clone(),
+            sprite: class.sprite,
+        }
+    }
+    /// Stats with equipment included; there are no buffs outside of battle
+    pub fn stats(&self) -> Stats {
+        derived_stats(self.base, self.gear, Stats::default())
+    }
+    pub fn equipped(&self, slot: Slot) -> Option<&str> {
+        self.equipment[slot as usize].as_deref()
+    }
+    /// Puts on an item (or takes off whatever's in the slot, for None), returning the
+    /// id of whatever used to be there.  Bonuses are looked up in items.
+    pub fn equip(&mut self, slot: Slot, item: Option<&str>, items: &Items) -> Option<String> {
+        let old = std::mem::replace(&mut self.equipment[slot as usize], item.map(String::from));
+        self.gear = self
+            .equipment
+            .iter()
+            .flatten()
+            .filter_map(|id| items.get(id))
+            .fold(Stats::default(), |gear, item| gear.plus(item.bonus));
+        let stats = self.stats();
+        self.hp = self.hp.min(stats.max_hp);
+        self.mp = self.mp.min(stats.max_mp);
+        old
+    }
+    pub fn combatant(&self) -> Combatant {
+        Combatant {
+            gear: self.gear,
+            hp: self.hp,
+            mp: self.mp,
+            ..Combatant::new(
+                &self.name,
+                Side::Party,
+                self.base,
+                self.skills.clone(),
+                self.sprite,
+            )
+        }
+    }
+    /// Picks up the HP and MP someone was left with after a battle (or an item)
+    pub fn sync(&mut self, fighter: &Combatant) {
+        let stats = self.stats();
+        self.hp = fighter.hp.min(stats.max_hp);
+        self.mp = fighter.mp.min(stats.max_mp);
+    }
+    /// Takes away HP outside of battle, like from walking over something harmful, which
+    /// leaves anyone still standing with at least 1
+    pub fn hurt(&mut self, amount: u32) {
+        if self.hp > 0 {
+            self.hp = self.hp.saturating_sub(amount).max(1);
+        }
+    }
+    pub fn restore(&mut self) {
+        let stats = self.stats();
+        self.hp = stats.max_hp;
+        self.mp = stats.max_mp;
+    }
+    /// Adds XP, growing into any levels it's enough for.  HP and MP go up by as much
+    /// as their maximums do.
+    pub fn gain_xp(&mut self, xp: u32, class: &ClassDef) -> Option<LevelUp> {
+        self.xp = self.xp.saturating_add(xp);
+        let level = class.level_for(self.xp).max(self.level);
+        if level == self.level {
+            return None;
+        }
+        let old = self.base;
+        let new = class.stats_at(level);
+        let gains = Stats {
+            max_hp: new.max_hp.saturating_sub(old.max_hp),
+            max_mp: new.max_mp.saturating_sub(old.max_mp),
+            attack: new.attack.saturating_sub(old.attack),
+            defense: new.defense.saturating_sub(old.defense),
+            speed: new.speed.saturating_sub(old.speed),
+        };
+        self.level = level;
+        self.base = new;
+        let stats = self.stats();
+        self.hp = (self.hp + gains.max_hp).min(stats.max_hp);
+        self.mp = (self.mp + gains.max_mp).min(stats.max_mp);
+        Some(LevelUp { level, gains })
+    }
+}
+
+/// Where the followers are standing, nearest to the leader first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trail {
+    spots: Vec<Vec2>,
+}
+
+impl Trail {
+    /// Followers standing in these spots, nearest to the leader first
+    pub fn with_spots(spots: Vec<Vec2>) -> Self {
+        Self { spots }
+    }
+    /// Everyone bunched up on the leader, like after walking through a door
+    pub fn gather(&mut self, leader: Vec2, followers: usize) {
+        self.spots.clear();
+        self.fit(leader, followers);
+    }
+    /// Lengthens or shortens the line to the number of followers; anyone new starts
+    /// out at the back of it (or on the leader, if there's nobody else)
+    pub fn fit(&mut self, leader: Vec2, followers: usize) {
+        let last = self.spots.last().copied().unwrap_or(leader);
+        self.spots.resize(followers, last);
+    }
+    /// The leader just stepped off of `from`: the first follower takes that spot and
+    /// everyone else moves up one
+    pub fn follow(&mut self, from: Vec2, followers: usize) {
+        self.spots.insert(0, from);
+        self.fit(from, followers);
+    }
+    pub fn spots(&self) -> &[Vec2] {
+        &self.spots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle::skill_named;
+
+    fn knight() -> ClassDef {
+        ClassDef {
+            name: "Knight".to_string(),
+            base: Stats {
+                max_hp: 30,
+                max_mp: 4,
+                attack: 8,
+                defense: 5,
+                speed: 3,
+            },
+            growth: Stats {
+                max_hp: 25,
+                max_mp: 0,
+                attack: 15,
+                defense: 10,
+                speed: 5,
+            },
+            xp: 10,
+            skills: vec![skill_named("heal").unwrap()],
+            sprite: SheetRegion::new(0, 0, 629, 14, 16, 16),
+        }
+    }
+
+    #[test]
+    fn test_new_member() {
+        let class = knight();
+        let hero = Member::new("Hero", "knight", &class);
+        assert_eq!(hero.level, 1);
+        assert_eq!(hero.stats(), class.base);
+        assert_eq!((hero.hp, hero.mp), (30, 4));
+        let fighter = hero.combatant();
+        assert_eq!(fighter.side, Side::Party);
+        assert_eq!(fighter.skills, class.skills);
+        assert_eq!((fighter.sprite.x, fighter.sprite.y), (0, 629));
+    }
+    #[test]
+    fn test_gain_xp() {
+        let class = knight();
+        let mut hero = Member::new("Hero", "knight", &class);
+        hero.hp = 10;
+        assert_eq!(hero.gain_xp(9, &class), None);
+        assert_eq!(hero.level, 1);
+        let up = hero.gain_xp(1, &class).unwrap();
+        assert_eq!(up.level, 2);
+        assert_eq!(
+            up.gains,
+            Stats {
+                max_hp: 2,
+                max_mp: 0,
+                attack: 1,
+                defense: 1,
+                speed: 0
+            }
+        );
+        assert_eq!(up.describe(), "HP +2 ATK +1 DEF +1");
+        assert_eq!(hero.base, class.stats_at(2));
+        // hurt members stay hurt, but get the new HP on top
+        assert_eq!(hero.hp, 12);
+        // enough XP for several levels at once rolls them all into one level-up
+        let up = hero.gain_xp(50, &class).unwrap();
+        assert_eq!(up.level, 4);
+        assert_eq!(up.gains.max_hp, 5);
+        assert_eq!(hero.xp, 60);
+        assert_eq!(hero.base, class.stats_at(4));
+    }
+    #[test]
+    fn test_equip() {
+        let class = knight();
+        let items = Items::parse(
+            "item sword weapon Sword\nbonus attack 4\nitem mail armor Mail\nbonus hp 10\nbonus defense 3\n",
+        )
+        .unwrap();
+        let mut hero = Member::new("Hero", "knight", &class);
+        assert_eq!(hero.equip(Slot::Weapon, Some("sword"), &items), None);
+        assert_eq!(hero.equip(Slot::Armor, Some("mail"), &items), None);
+        assert_eq!(hero.equipped(Slot::Weapon), Some("sword"));
+        assert_eq!(
+            hero.stats(),
+            Stats {
+                max_hp: 40,
+                max_mp: 4,
+                attack: 12,
+                defense: 8,
+                speed: 3
+            }
+        );
+        // battles see the same stats
+        assert_eq!(hero.combatant().stats(), hero.stats());
+        hero.restore();
+        assert_eq!(hero.hp, 40);
+        // taking the armor off takes its HP with it
+        assert_eq!(
+            hero.equip(Slot::Armor, None, &items),
+            Some("mail".to_string())
+        );
+        assert_eq!(hero.stats().defense, 5);
+        assert_eq!(hero.hp, 30);
+    }
+    #[test]
+    fn test_sync() {
+        let class = knight();
+        let mut hero = Member::new("Hero", "knight", &class);
+        let mut fighter = hero.combatant();
+        fighter.hp = 3;
+        fighter.mp = 1;
+        hero.sync(&fighter);
+        assert_eq!((hero.hp, hero.mp), (3, 1));
+        assert_eq!(hero.combatant().hp, 3);
+        hero.restore();
+        assert_eq!((hero.hp, hero.mp), (30, 4));
+    }
+    #[test]
+    fn test_hurt() {
+        let class = knight();
+        let mut hero = Member::new("Hero", "knight", &class);
+        hero.hurt(12);
+        assert_eq!(hero.hp, 18);
+        hero.hurt(100);
+        assert_eq!(hero.hp, 1);
+        hero.hp = 0;
+        hero.hurt(1);
+        assert_eq!(hero.hp, 0);
+    }
+    #[test]
+    fn test_trail() {
+        let at = |x, y| Vec2 { x, y };
+        let mut trail = Trail::default();
+        trail.gather(at(3, 3), 2);
+        assert_eq!(trail.spots(), &[at(3, 3), at(3, 3)]);
+        // followers fan out one step at a time
+        trail.follow(at(3, 3), 2);
+        assert_eq!(trail.spots(), &[at(3, 3), at(3, 3)]);
+        trail.follow(at(4, 3), 2);
+        assert_eq!(trail.spots(), &[at(4, 3), at(3, 3)]);
+        trail.follow(at(5, 3), 2);
+        assert_eq!(trail.spots(), &[at(5, 3), at(4, 3)]);
+        // a new member shows up at the back
+        trail.fit(at(6, 3), 3);
+        assert_eq!(trail.spots(), &[at(5, 3), at(4, 3), at(4, 3)]);
+        trail.follow(at(6, 3), 3);
+        assert_eq!(trail.spots(), &[at(6, 3), at(5, 3), at(4, 3)]);
+        trail.follow(at(6, 4), 3);
+        assert_eq!(trail.spots(), &[at(6, 4), at(6, 3), at(5, 3)]);
+        trail.gather(at(1, 1), 3);
+        assert_eq!(trail.spots(), &[at(1, 1); 3]);
+        // with nobody following, there's no trail
+        trail.follow(at(1, 2), 0);
+        assert!(trail.spots().is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::geom::Vec2;
+use crate::level::Level;
+use rand::Rng;
+
+/// How close (in steps) the player has to be before an enemy gives chase
+pub const CHASE_RANGE: u16 = 4;
+
+fn step(pos: Vec2, dx: i32, dy: i32) -> Option<Vec2> {
+    Some(Vec2 {
+        x: u16::try_from(pos.x as i32 + dx).ok()?,
+        y: u16::try_from(pos.y as i32 + dy).ok()?,
+    })
+}
+
+/// Where an enemy at `pos` moves next.  Within CHASE_RANGE of the player it heads
+/// straight for them (possibly right onto them, which is how battles start), and
+/// otherwise it wanders randomly or stays put.  It never walks anywhere the tile flags
+/// don't let it (including water, which enemies can't cross) or any spot `blocked`
+/// says is taken, and that goes for the player's spot too: a player out on the water
+/// can't be caught.
+pub fn roam_step(
+    level: &Level,
+    pos: Vec2,
+    player: Vec2,
+    blocked: impl Fn(Vec2) -> bool,
+    rng: &mut impl Rng,
+) -> Vec2 {
+    let here = level
+        .get_tile(pos)
+        .map(|tile| tile.flags)
+        .unwrap_or_default();
+    let open = |dest: Vec2| {
+        let dir = (dest.x as i32 - pos.x as i32, dest.y as i32 - pos.y as i32);
+        let enterable = level
+            .get_tile(dest)
+            .is_some_and(|tile| tile.flags.can_enter(dir, false));
+        here.can_leave(dir) && enterable && (dest == player || !blocked(dest))
+    };
+    let (dx, dy) = (
+        player.x as i32 - pos.x as i32,
+        player.y as i32 - pos.y as i32,
+    );
+    if dx.unsigned_abs() + dy.unsigned_abs() <= CHASE_RANGE as u32 {
+        // close the bigger gap first, but go around the other way if that's blocked
+        let horizontal = (dx.signum(), 0);
+        let vertical = (0, dy.signum());
+        let tries = if dx.abs() >= dy.abs() {
+            [horizontal, vertical]
+        } else {
+            [vertical, horizontal]
+        };
+        return tries
+            .into_iter()
+            .filter(|&d| d != (0, 0))
+            .filter_map(|(dx, dy)| step(pos, dx, dy))
+            .find(|&dest| open(dest))
+            .unwrap_or(pos);
+    }
+    let (dx, dy) = [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)][rng.gen_range(0..5)];
+    step(pos, dx, dy).filter(|&dest| open(dest)).unwrap_or(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, SeedableRng};
+    const FIELD: &str = "field 6 3
+===
+. o 85 0 16 16
+# s 289 357 16 16
+===
+. . . . . .
+. # . . . .
+. . . . . #
+===
+";
+    #[test]
+    fn test_chase() {
+        let level = Level::parse(FIELD).unwrap();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let at = |x, y| Vec2 { x, y };
+        // straight at the player along the longer axis
+        assert_eq!(
+            roam_step(&level, at(0, 0), at(3, 1), |_| false, &mut rng),
+            at(1, 0)
+        );
+        // the wall's in the way, and there's no other direction that gets closer
+        assert_eq!(
+            roam_step(&level, at(0, 1), at(3, 1), |_| false, &mut rng),
+            at(0, 1)
+        );
+        // but if the player's off to the side too, go around
+        assert_eq!(
+            roam_step(&level, at(0, 1), at(3, 2), |_| false, &mut rng),
+            at(0, 2)
+        );
+        // right onto the player
+        assert_eq!(
+            roam_step(&level, at(2, 1), at(3, 1), |_| false, &mut rng),
+            at(3, 1)
+        );
+        // someone else is in the way
+        assert_eq!(
+            roam_step(&level, at(2, 0), at(4, 0), |p| p == at(3, 0), &mut rng),
+            at(2, 0)
+        );
+        // water's in the way too
+        let level = Level::parse(&FIELD.replace("# s", "# w")).unwrap();
+        assert_eq!(
+            roam_step(&level, at(0, 1), at(3, 1), |_| false, &mut rng),
+            at(0, 1)
+        );
+        // even when the player's the one out on it
+        assert_eq!(
+            roam_step(&level, at(1, 0), at(1, 1), |_| false, &mut rng),
+            at(1, 0)
+        );
+    }
+    #[test]
+    fn test_wander() {
+        let level = Level::parse(FIELD).unwrap();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let far = Vec2 { x: 99, y: 99 };
+        let mut pos = Vec2 { x: 2, y: 0 };
+        let mut moved = false;
+        for _ in 0..100 {
+            let next = roam_step(&level, pos, far, |_| false, &mut rng);
+            assert!(pos.x.abs_diff(next.x) + pos.y.abs_diff(next.y) <= 1);
+            assert!(!level.get_tile(next).unwrap().flags.solid);
+            moved |= next != pos;
+            pos = next;
+        }
+        assert!(moved);
+    }
+}
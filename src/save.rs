@@ -0,0 +1,560 @@
+use crate::classes::Classes;
+use crate::geom::Vec2;
+use crate::items::{ItemKind, Items};
+use crate::level::Level;
+use crate::parse::{FieldError, Fields};
+use crate::party::Member;
+use std::path::{Path, PathBuf};
+
+/*
+A saved game is a text file, one per save slot, in a line-oriented format like the
+content files:
+
+save VERSION
+level LEVELNAME
+player X Y FACING-X FACING-Y
+follower X Y
+member CLASS LEVEL XP HP MP NAME
+equip ITEM
+item ITEM COUNT
+flag FLAG
+state LEVELNAME
+rebind START DIALOG-ID
+defeated START
+opened START
+
+The first line says which version of the format the rest of the file is in; files
+from other versions aren't loaded.  `level` is where the player is, and `player` is
+where they're standing and which way they're facing.  Each `follower` is where one
+of the party members behind them is, nearest first.  `member` lines list the party
+in order, each followed by an `equip` line for whatever it's wearing.  `state` starts
+the list of what's changed in a level: NPCs that now use different dialog, enemies
+that have been beaten and chests that have been opened, all by their index among the
+level's starting entities.
+
+Stats aren't saved, since they follow from each member's class, level and equipment.
+Blank lines and lines starting with # are ignored.
+*/
+
+/// The version of the format that `Save` writes and reads
+pub const SAVE_VERSION: u32 = 1;
+
+/// How many save slots there are
+pub const SAVE_SLOTS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedMember {
+    pub class: String,
+    pub name: String,
+    pub level: u32,
+    pub xp: u32,
+    pub hp: u32,
+    pub mp: u32,
+    // item ids of whatever's equipped
+    pub equipment: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SavedLevel {
+    // (npc start index, dialog id)
+    pub npc_dialogs: Vec<(usize, usize)>,
+    pub defeated_enemies: Vec<usize>,
+    pub opened_chests: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Save {
+    pub level: String,
+    pub player: Vec2,
+    pub facing: (i32, i32),
+    pub followers: Vec<Vec2>,
+    pub party: Vec<SavedMember>,
+    pub inventory: Vec<(String, u32)>,
+    pub flags: Vec<String>,
+    pub levels: Vec<(String, SavedLevel)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveParseError {
+    Field(FieldError),
+    UnknownKeyword {
+        line: usize,
+        col: usize,
+        keyword: String,
+    },
+    // the file doesn't start with a save line
+    NotASave {
+        line: usize,
+        col: usize,
+    },
+    WrongVersion {
+        line: usize,
+        col: usize,
+        version: u32,
+    },
+    UnknownLevel {
+        line: usize,
+        col: usize,
+        name: String,
+    },
+    OutsideMember {
+        line: usize,
+        col: usize,
+    },
+    OutsideState {
+        line: usize,
+        col: usize,
+    },
+    Missing {
+        line: usize,
+        col: usize,
+        keyword: &'static str,
+    },
+}
+
+impl SaveParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Field(err) => err.line(),
+            Self::UnknownKeyword { line, .. }
+            | Self::NotASave { line, .. }
+            | Self::WrongVersion { line, .. }
+            | Self::UnknownLevel { line, .. }
+            | Self::OutsideMember { line, .. }
+            | Self::OutsideState { line, .. }
+            | Self::Missing { line, .. } => *line,
+        }
+    }
+    pub fn col(&self) -> usize {
+        match self {
+            Self::Field(err) => err.col(),
+            Self::UnknownKeyword { col, .. }
+            | Self::NotASave { col, .. }
+            | Self::WrongVersion { col, .. }
+            | Self::UnknownLevel { col, .. }
+            | Self::OutsideMember { col, .. }
+            | Self::OutsideState { col, .. }
+            | Self::Missing { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::Field(err) => err.message(),
+            Self::UnknownKeyword { keyword, .. } => {
+                format!("unexpected keyword {keyword:?}")
+            }
+            Self::NotASave { .. } => "this isn't a save file".to_string(),
+            Self::WrongVersion { version, .. } => format!(
+                "this save is from version {version}, but only version {SAVE_VERSION} can be loaded"
+            ),
+            Self::UnknownLevel { name, .. } => format!("there's no level called {name} anymore"),
+            Self::OutsideMember { .. } => "this needs to come after a member line".to_string(),
+            Self::OutsideState { .. } => "this needs to come after a state line".to_string(),
+            Self::Missing { keyword, .. } => format!("there's no {keyword} line"),
+        }
+    }
+}
+
+impl std::fmt::Display for SaveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for SaveParseError {}
+
+impl From<FieldError> for SaveParseError {
+    fn from(err: FieldError) -> Self {
+        Self::Field(err)
+    }
+}
+
+impl SavedMember {
+    pub fn new(member: &Member) -> Self {
+        Self {
+            class: member.class.clone(),
+            name: member.name.clone(),
+            level: member.level,
+            xp: member.xp,
+            hp: member.hp,
+            mp: member.mp,
+            equipment: member.equipment.iter().flatten().cloned().collect(),
+        }
+    }
+    /// Puts the member back together, or None if their class is gone.  Equipment that
+    /// isn't in items anymore gets left off.
+    pub fn member(&self, classes: &Classes, items: &Items) -> Option<Member> {
+        let class = classes.get(&self.class)?;
+        let mut member = Member::new(&self.name, &self.class, class);
+        member.level = self.level;
+        member.xp = self.xp;
+        member.base = class.stats_at(self.level);
+        for id in self.equipment.iter() {
+            if let Some(ItemKind::Equipment(slot)) = items.get(id).map(|item| item.kind) {
+                member.equip(slot, Some(id), items);
+            }
+        }
+        let stats = member.stats();
+        member.hp = self.hp.min(stats.max_hp);
+        member.mp = self.mp.min(stats.max_mp);
+        Some(member)
+    }
+}
+
+impl Save {
+    /// Reads a save, checking that every level it mentions is one that `is_level` knows
+    pub fn parse(s: &str, is_level: impl Fn(&str) -> bool) -> Result<Self, SaveParseError> {
+        let mut version = None;
+        let mut level = None;
+        let mut player = None;
+        let mut save = Save {
+            level: String::new(),
+            player: Vec2 { x: 0, y: 0 },
+            facing: (0, 1),
+            followers: vec![],
+            party: vec![],
+            inventory: vec![],
+            flags: vec![],
+            levels: vec![],
+        };
+        let mut line_no = 0;
+        for line in s.lines() {
+            line_no += 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut fields = Fields::new(line_no, line);
+            let (col, keyword) = fields.chunk("keyword")?;
+            if version.is_none() {
+                if keyword != "save" {
+                    return Err(SaveParseError::NotASave { line: line_no, col });
+                }
+                let (col, _) = fields.peek().unwrap_or((col, ""));
+                let number: u32 = fields.num("version")?;
+                if number != SAVE_VERSION {
+                    return Err(SaveParseError::WrongVersion {
+                        line: line_no,
+                        col,
+                        version: number,
+                    });
+                }
+                version = Some(number);
+                fields.finish()?;
+                continue;
+            }
+            let known_level = |fields: &mut Fields| {
+                let (col, name) = fields.chunk("level name")?;
+                if !is_level(name) {
+                    return Err(SaveParseError::UnknownLevel {
+                        line: line_no,
+                        col,
+                        name: name.to_string(),
+                    });
+                }
+                Ok(name.to_string())
+            };
+            match keyword {
+                "level" => level = Some(known_level(&mut fields)?),
+                "player" => {
+                    player = Some(Vec2 {
+                        x: fields.num("player x")?,
+                        y: fields.num("player y")?,
+                    });
+                    save.facing = (fields.num("facing x")?, fields.num("facing y")?);
+                }
+                "follower" => save.followers.push(Vec2 {
+                    x: fields.num("follower x")?,
+                    y: fields.num("follower y")?,
+                }),
+                "member" => save.party.push(SavedMember {
+                    class: fields.chunk("class id")?.1.to_string(),
+                    level: fields.num("level")?,
+                    xp: fields.num("xp")?,
+                    hp: fields.num("HP")?,
+                    mp: fields.num("MP")?,
+                    name: fields.rest("name")?.1.to_string(),
+                    equipment: vec![],
+                }),
+                "equip" => {
+                    let Some(member) = save.party.last_mut() else {
+                        return Err(SaveParseError::OutsideMember { line: line_no, col });
+                    };
+                    member
+                        .equipment
+                        .push(fields.chunk("item id")?.1.to_string());
+                }
+                "item" => {
+                    let item = fields.chunk("item id")?.1.to_string();
+                    save.inventory.push((item, fields.num("item count")?));
+                }
+                "flag" => save.flags.push(fields.chunk("flag")?.1.to_string()),
+                "state" => {
+                    let name = known_level(&mut fields)?;
+                    save.levels.push((name, SavedLevel::default()));
+                }
+                "rebind" | "defeated" | "opened" => {
+                    let Some((_, state)) = save.levels.last_mut() else {
+                        return Err(SaveParseError::OutsideState { line: line_no, col });
+                    };
+                    match keyword {
+                        "rebind" => state
+                            .npc_dialogs
+                            .push((fields.num("npc start")?, fields.num("dialog id")?)),
+                        "defeated" => state.defeated_enemies.push(fields.num("enemy start")?),
+                        _ => state.opened_chests.push(fields.num("chest start")?),
+                    }
+                }
+                _ => {
+                    return Err(SaveParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    })
+                }
+            }
+            fields.finish()?;
+        }
+        let missing = |keyword| SaveParseError::Missing {
+            line: line_no + 1,
+            col: 1,
+            keyword,
+        };
+        if version.is_none() {
+            return Err(SaveParseError::NotASave {
+                line: line_no + 1,
+                col: 1,
+            });
+        }
+        save.level = level.ok_or(missing("level"))?;
+        save.player = player.ok_or(missing("player"))?;
+        if save.party.is_empty() {
+            return Err(missing("member"));
+        }
+        Ok(save)
+    }
+    /// Squares the save up with `level` (the one it's on) as it is now, in case the content
+    /// has changed since it was made: a player off the edge of the level goes back to its
+    /// player start, and followers off the edge and rebinds to dialogs that aren't there
+    /// anymore (per `is_dialog`) are dropped.  Errs if there's nowhere to put the player.
+    pub fn fit(&mut self, level: &Level, is_dialog: impl Fn(usize) -> bool) -> Result<(), String> {
+        if level.get_tile(self.player).is_none() {
+            let Vec2 { x, y } = self.player;
+            self.player = level.player_start().ok_or(format!(
+                "({x}, {y}) is off the edge of {}, which has no player start",
+                self.level
+            ))?;
+            // they'd be left behind wherever the player used to be
+            self.followers.clear();
+        }
+        self.followers.retain(|pos| level.get_tile(*pos).is_some());
+        for (_, state) in self.levels.iter_mut() {
+            state.npc_dialogs.retain(|(_, dialog)| is_dialog(*dialog));
+        }
+        Ok(())
+    }
+    /// What to show for this save in a list of slots
+    pub fn summary(&self) -> String {
+        let leader = &self.party[0];
+        format!("{} Lv{} {}", leader.name, leader.level, self.level)
+    }
+}
+
+impl std::fmt::Display for Save {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "save {SAVE_VERSION}")?;
+        writeln!(f, "level {}", self.level)?;
+        let (player, (dx, dy)) = (self.player, self.facing);
+        writeln!(f, "player {} {} {dx} {dy}", player.x, player.y)?;
+        for follower in self.followers.iter() {
+            writeln!(f, "follower {} {}", follower.x, follower.y)?;
+        }
+        for m in self.party.iter() {
+            writeln!(
+                f,
+                "member {} {} {} {} {} {}",
+                m.class, m.level, m.xp, m.hp, m.mp, m.name
+            )?;
+            for item in m.equipment.iter() {
+                writeln!(f, "equip {item}")?;
+            }
+        }
+        for (item, count) in self.inventory.iter() {
+            writeln!(f, "item {item} {count}")?;
+        }
+        for flag in self.flags.iter() {
+            writeln!(f, "flag {flag}")?;
+        }
+        for (name, state) in self.levels.iter() {
+            writeln!(f, "state {name}")?;
+            for (npc, dlg) in state.npc_dialogs.iter() {
+                writeln!(f, "rebind {npc} {dlg}")?;
+            }
+            for start in state.defeated_enemies.iter() {
+                writeln!(f, "defeated {start}")?;
+            }
+            for start in state.opened_chests.iter() {
+                writeln!(f, "opened {start}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where a save slot (counting from 1) is kept in the save directory
+pub fn slot_path(dir: &Path, slot: usize) -> PathBuf {
+    dir.join(format!("slot{slot}.txt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "save 1
+# where we are
+level town
+player 4 5 -1 0
+follower 5 5
+member hero 3 45 20 0 Hero
+equip sword
+member mage 1 0 18 12 Mira Vale
+item potion 2
+flag met_hermit
+state town
+rebind 0 4
+opened 7
+state cave
+defeated 2
+defeated 3
+";
+
+    fn is_level(name: &str) -> bool {
+        ["town", "cave"].contains(&name)
+    }
+
+    #[test]
+    fn test_parse_save() {
+        let save = Save::parse(SAMPLE, is_level).unwrap();
+        assert_eq!(save.level, "town");
+        assert_eq!(save.player, Vec2 { x: 4, y: 5 });
+        assert_eq!(save.facing, (-1, 0));
+        assert_eq!(save.followers, vec![Vec2 { x: 5, y: 5 }]);
+        assert_eq!(
+            save.party[1],
+            SavedMember {
+                class: "mage".to_string(),
+                name: "Mira Vale".to_string(),
+                level: 1,
+                xp: 0,
+                hp: 18,
+                mp: 12,
+                equipment: vec![],
+            }
+        );
+        assert_eq!(save.party[0].equipment, vec!["sword"]);
+        assert_eq!(save.inventory, vec![("potion".to_string(), 2)]);
+        assert_eq!(save.flags, vec!["met_hermit"]);
+        assert_eq!(
+            save.levels,
+            vec![
+                (
+                    "town".to_string(),
+                    SavedLevel {
+                        npc_dialogs: vec![(0, 4)],
+                        defeated_enemies: vec![],
+                        opened_chests: vec![7],
+                    }
+                ),
+                (
+                    "cave".to_string(),
+                    SavedLevel {
+                        npc_dialogs: vec![],
+                        defeated_enemies: vec![2, 3],
+                        opened_chests: vec![],
+                    }
+                ),
+            ]
+        );
+        assert_eq!(save.summary(), "Hero Lv3 town");
+        // writing it back out and reading it in again gets the same thing
+        assert_eq!(Save::parse(&save.to_string(), is_level).unwrap(), save);
+    }
+    #[test]
+    fn test_fit_save() {
+        let town =
+            Level::parse("town 3 2\n===\n. o 85 0 16 16\n===\n. . .\n. . .\n===\nplayer 1 0\n")
+                .unwrap();
+        let mut save = Save::parse(SAMPLE, is_level).unwrap();
+        save.followers.push(Vec2 { x: 2, y: 1 });
+        // the town got smaller since this was saved, and dialog 4 is gone
+        save.fit(&town, |id| id != 4).unwrap();
+        assert_eq!(save.player, Vec2 { x: 1, y: 0 });
+        assert_eq!(save.followers, vec![]);
+        assert_eq!(save.levels[0].1.npc_dialogs, vec![]);
+        // somewhere that's still there is left alone, but stragglers off the edge aren't
+        save.followers = vec![
+            Vec2 { x: 2, y: 1 },
+            Vec2 { x: 3, y: 1 },
+            Vec2 { x: 0, y: 2 },
+        ];
+        save.player = Vec2 { x: 2, y: 0 };
+        save.fit(&town, |_| true).unwrap();
+        assert_eq!(save.player, Vec2 { x: 2, y: 0 });
+        assert_eq!(save.followers, vec![Vec2 { x: 2, y: 1 }]);
+        // with no player start there's nowhere to put them
+        let nowhere = Level::parse("town 1 1\n===\n. o 85 0 16 16\n===\n.\n===\n").unwrap();
+        save.player = Vec2 { x: 4, y: 5 };
+        assert_eq!(
+            save.fit(&nowhere, |_| true).unwrap_err(),
+            "(4, 5) is off the edge of town, which has no player start"
+        );
+    }
+    #[test]
+    fn test_saved_member() {
+        let classes = Classes::parse(
+            "class hero Hero\nbase 20 5 4 3 2\ngrowth 20 5 4 3 2\nxp 10\nsprite 0 578\n",
+        )
+        .unwrap();
+        let items = Items::parse("item sword weapon Sword\nbonus attack 4\n").unwrap();
+        let mut hero = Member::new("Hero", "hero", classes.get("hero").unwrap());
+        hero.gain_xp(100, classes.get("hero").unwrap());
+        hero.equip(crate::items::Slot::Weapon, Some("sword"), &items);
+        hero.hp = 7;
+        let saved = SavedMember::new(&hero);
+        assert_eq!(saved.equipment, vec!["sword"]);
+        let loaded = saved.member(&classes, &items).unwrap();
+        assert_eq!(loaded.level, hero.level);
+        assert_eq!(loaded.stats(), hero.stats());
+        assert_eq!((loaded.hp, loaded.mp), (7, hero.mp));
+        // there's no putting back together someone whose class is gone
+        assert!(saved.member(&Classes::default(), &items).is_none());
+    }
+    #[test]
+    fn test_parse_save_errors() {
+        let err = |s: &str| Save::parse(s, is_level).unwrap_err().to_string();
+        assert_eq!(
+            err(&SAMPLE.replace("state cave", "state mine")),
+            "14:7: there's no level called mine anymore"
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("level town", "level mine")),
+            "3:7: there's no level called mine anymore"
+        );
+        assert_eq!(
+            err(&SAMPLE.replace("save 1", "save 2")),
+            "1:6: this save is from version 2, but only version 1 can be loaded"
+        );
+        assert_eq!(err("level town\n"), "1:1: this isn't a save file");
+        assert_eq!(err(""), "1:1: this isn't a save file");
+        assert_eq!(
+            err("save 1\nequip sword\n"),
+            "2:1: this needs to come after a member line"
+        );
+        assert_eq!(
+            err("save 1\nopened 3\n"),
+            "2:1: this needs to come after a state line"
+        );
+        assert_eq!(err("save 1\nlevel town\n"), "3:1: there's no player line");
+        assert_eq!(err("save 1\nplayer 1 2\n"), "2:11: expected facing x");
+        assert_eq!(err("save 1\nhp 3\n"), "2:1: unexpected keyword \"hp\"");
+    }
+}
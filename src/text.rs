@@ -0,0 +1,289 @@
+use frenderer::bitfont::BitFont;
+use frenderer::sprites::{SheetRegion, Transform};
+
+/*
+Laying out text to fit in boxes: wrapping it at word boundaries to a width, splitting
+the lines into boxfuls, and revealing them a character at a time.
+
+Widths come from measuring the font itself, since BitFont doesn't say how wide its
+characters are; it draws every character the same width, except that whitespace
+takes up extra room.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextMetrics {
+    // how far along one character moves the next
+    pub advance: f32,
+    // how far along a space (or other whitespace) moves the next character
+    pub space: f32,
+}
+
+impl TextMetrics {
+    /// Measures a font drawn with characters `char_height` tall
+    pub fn measure(font: &BitFont, char_height: f32) -> Self {
+        let mut trfs = [Transform::ZERO; 3];
+        let mut uvs = [SheetRegion::ZERO; 3];
+        let mut end = |text: &str| {
+            font.draw_text(&mut trfs, &mut uvs, text, [0.0, 0.0], 0, char_height)
+                .0[0]
+        };
+        let (one, two, spaced) = (end("M"), end("MM"), end("M M"));
+        Self {
+            advance: two - one,
+            space: spaced - two,
+        }
+    }
+    /// How wide a line of text comes out
+    pub fn width(&self, text: &str) -> f32 {
+        text.chars().map(|c| self.char_width(c)).sum()
+    }
+    fn char_width(&self, c: char) -> f32 {
+        if c.is_whitespace() {
+            self.space
+        } else {
+            self.advance
+        }
+    }
+}
+
+/// Breaks text into lines no wider than `width`, at spaces where possible and in the
+/// middle of a word only when the word doesn't fit on a line by itself.  Newlines in
+/// the text always start a new line.
+pub fn wrap(text: &str, width: f32, metrics: TextMetrics) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    wrap_indices(text, width, metrics)
+        .iter()
+        .map(|line| line.iter().map(|&i| chars[i]).collect())
+        .collect()
+}
+
+/// Like `wrap`, but with the index of each character on each line instead of the
+/// characters themselves, for keeping track of anything that goes along with them
+pub fn wrap_indices(text: &str, width: f32, metrics: TextMetrics) -> Vec<Vec<usize>> {
+    let chars: Vec<char> = text.chars().collect();
+    let line_width =
+        |line: &[usize]| -> f32 { line.iter().map(|&i| metrics.char_width(chars[i])).sum() };
+    let mut lines = vec![];
+    let mut line = vec![];
+    let mut i = 0;
+    while i <= chars.len() {
+        if i == chars.len() || chars[i] == '\n' {
+            lines.push(std::mem::take(&mut line));
+            i += 1;
+            continue;
+        }
+        if chars[i] == ' ' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && !matches!(chars[i], ' ' | '\n') {
+            i += 1;
+        }
+        // words are joined up by the space in front of them
+        let mut joined = line.clone();
+        if !line.is_empty() {
+            joined.push(start - 1);
+        }
+        joined.extend(start..i);
+        if line_width(&joined) <= width {
+            line = joined;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        // chop up anything too long to fit on a line of its own
+        for (c, ch) in chars.iter().enumerate().take(i).skip(start) {
+            if !line.is_empty() && line_width(&line) + metrics.char_width(*ch) > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    lines
+}
+
+/// Splits lines into boxfuls of `per_page` lines each; there's always at least one,
+/// even if it's empty
+pub fn paginate<T: Clone>(lines: &[T], per_page: usize) -> Vec<Vec<T>> {
+    if lines.is_empty() {
+        return vec![vec![]];
+    }
+    lines
+        .chunks(per_page.max(1))
+        .map(|page| page.to_vec())
+        .collect()
+}
+
+/// The first `count` characters of some lines, spread across them in order
+pub fn reveal(lines: &[String], count: usize) -> Vec<String> {
+    let mut left = count;
+    lines
+        .iter()
+        .map(|line| {
+            let shown: String = line.chars().take(left).collect();
+            left -= shown.chars().count();
+            shown
+        })
+        .collect()
+}
+
+/// Shows text a character at a time, so many characters per second, or all at once
+/// without a speed.  It can stop for a while partway through, too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Typewriter {
+    total: usize,
+    shown: f32,
+    speed: Option<f32>,
+    // (how many characters in, seconds to wait there), in order
+    pauses: Vec<(usize, f32)>,
+    // the pause coming up next, and how long it's been waited on so far
+    next_pause: usize,
+    waited: f32,
+}
+
+impl Typewriter {
+    pub fn new(total: usize, speed: Option<f32>) -> Self {
+        Self::with_pauses(total, speed, vec![])
+    }
+    pub fn with_pauses(total: usize, speed: Option<f32>, pauses: Vec<(usize, f32)>) -> Self {
+        Self {
+            total,
+            shown: if speed.is_some() { 0.0 } else { total as f32 },
+            speed,
+            pauses,
+            next_pause: 0,
+            waited: 0.0,
+        }
+    }
+    /// Moves things along by `dt` seconds
+    pub fn tick(&mut self, mut dt: f32) {
+        let Some(speed) = self.speed else {
+            return;
+        };
+        while dt > 0.0 && !self.done() {
+            let pause = self.pauses.get(self.next_pause).copied();
+            match pause {
+                Some((at, secs)) if self.shown >= at as f32 => {
+                    let wait = (secs - self.waited).min(dt);
+                    self.waited += wait;
+                    dt -= wait;
+                    if self.waited >= secs {
+                        self.next_pause += 1;
+                        self.waited = 0.0;
+                    }
+                }
+                _ => {
+                    let stop = pause.map_or(self.total, |(at, _)| at.min(self.total)) as f32;
+                    let to_stop = (stop - self.shown) / speed;
+                    if to_stop <= dt {
+                        self.shown = stop;
+                        dt -= to_stop;
+                    } else {
+                        self.shown += speed * dt;
+                        dt = 0.0;
+                    }
+                }
+            }
+        }
+    }
+    /// Shows everything right away
+    pub fn skip(&mut self) {
+        self.shown = self.total as f32;
+    }
+    /// How many characters are showing
+    pub fn shown(&self) -> usize {
+        self.shown as usize
+    }
+    pub fn done(&self) -> bool {
+        self.shown() >= self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 8 pixels a character and 16 a space, like the game's font
+    const METRICS: TextMetrics = TextMetrics {
+        advance: 8.0,
+        space: 16.0,
+    };
+
+    #[test]
+    fn test_measure() {
+        let font =
+            BitFont::with_sheet_region(' '..='~', SheetRegion::rect(0, 738, 288, 765), 8, 8, 1, 1);
+        assert_eq!(TextMetrics::measure(&font, 8.0), METRICS);
+        assert_eq!(METRICS.width("hi there"), 8.0 * 7.0 + 16.0);
+    }
+    #[test]
+    fn test_wrap() {
+        // "the quick" is 8 characters and a space, 80 pixels
+        assert_eq!(
+            wrap("the quick brown fox", 80.0, METRICS),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(
+            wrap("the quick brown fox", 79.0, METRICS),
+            vec!["the", "quick", "brown", "fox"]
+        );
+        assert_eq!(
+            wrap("one\ntwo  three", 200.0, METRICS),
+            vec!["one", "two three"]
+        );
+        // words longer than a line get split up
+        assert_eq!(
+            wrap("a abcdefghijk", 40.0, METRICS),
+            vec!["a", "abcde", "fghij", "k"]
+        );
+        assert_eq!(wrap("", 40.0, METRICS), vec![""]);
+        // the space joining two words is the one right before the second
+        assert_eq!(
+            wrap_indices("ab  c\nd", 200.0, METRICS),
+            vec![vec![0, 1, 3, 4], vec![6]]
+        );
+    }
+    #[test]
+    fn test_paginate() {
+        let lines: Vec<String> = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+        let pages = paginate(&lines, 2);
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[2], vec!["e"]);
+        assert_eq!(paginate::<String>(&[], 2), vec![Vec::<String>::new()]);
+    }
+    #[test]
+    fn test_reveal() {
+        let lines: Vec<String> = ["hello", "there"].map(String::from).to_vec();
+        assert_eq!(reveal(&lines, 0), vec!["", ""]);
+        assert_eq!(reveal(&lines, 7), vec!["hello", "th"]);
+        assert_eq!(reveal(&lines, 70), lines);
+    }
+    #[test]
+    fn test_typewriter() {
+        let mut typewriter = Typewriter::new(10, Some(20.0));
+        assert_eq!(typewriter.shown(), 0);
+        typewriter.tick(0.1);
+        assert_eq!(typewriter.shown(), 2);
+        assert!(!typewriter.done());
+        typewriter.tick(1.0);
+        assert_eq!(typewriter.shown(), 10);
+        assert!(typewriter.done());
+        let mut typewriter = Typewriter::new(10, Some(20.0));
+        typewriter.skip();
+        assert!(typewriter.done());
+        // without a speed everything shows up at once
+        assert!(Typewriter::new(10, None).done());
+        // stopping for half a second after two characters
+        let mut typewriter = Typewriter::with_pauses(10, Some(20.0), vec![(2, 0.5)]);
+        typewriter.tick(0.1);
+        assert_eq!(typewriter.shown(), 2);
+        typewriter.tick(0.3);
+        assert_eq!(typewriter.shown(), 2);
+        typewriter.tick(0.3);
+        assert_eq!(typewriter.shown(), 4);
+        typewriter.skip();
+        assert!(typewriter.done());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::parse::{FieldError, Fields};
+
+/*
+The world manifest lists which level files make up the game and which level the player starts in:
+
+start LEVELNAME
+level ASSET
+level ASSET
+level ASSET
+
+ASSET is the name of a level file in content/ without its extension (so `level1` for `level1.txt`),
+while LEVELNAME is the name given on the first line of a level file, which is also what doors use.
+Blank lines and lines starting with # are ignored.
+*/
+pub struct World {
+    start: String,
+    start_line: usize,
+    levels: Vec<String>,
+    level_lines: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorldParseError {
+    Field(FieldError),
+    UnknownKeyword {
+        line: usize,
+        col: usize,
+        keyword: String,
+    },
+    DuplicateStart {
+        line: usize,
+        col: usize,
+    },
+    MissingStart {
+        line: usize,
+        col: usize,
+    },
+}
+
+impl WorldParseError {
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Field(err) => err.line(),
+            Self::UnknownKeyword { line, .. }
+            | Self::DuplicateStart { line, .. }
+            | Self::MissingStart { line, .. } => *line,
+        }
+    }
+    pub fn col(&self) -> usize {
+        match self {
+            Self::Field(err) => err.col(),
+            Self::UnknownKeyword { col, .. }
+            | Self::DuplicateStart { col, .. }
+            | Self::MissingStart { col, .. } => *col,
+        }
+    }
+    pub fn message(&self) -> String {
+        match self {
+            Self::Field(err) => err.message(),
+            Self::UnknownKeyword { keyword, .. } => {
+                format!("expected start or level, not {keyword:?}")
+            }
+            Self::DuplicateStart { .. } => "the starting level is already set".to_string(),
+            Self::MissingStart { .. } => "no starting level given".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for WorldParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line(), self.col(), self.message())
+    }
+}
+
+impl std::error::Error for WorldParseError {}
+
+impl From<FieldError> for WorldParseError {
+    fn from(err: FieldError) -> Self {
+        Self::Field(err)
+    }
+}
+
+impl World {
+    pub fn parse(s: &str) -> Result<Self, WorldParseError> {
+        let mut start = None;
+        let mut start_line = 0;
+        let mut levels = vec![];
+        let mut level_lines = vec![];
+        let mut line_no = 0;
+        for line in s.lines() {
+            line_no += 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut fields = Fields::new(line_no, line);
+            let (col, keyword) = fields.chunk("start or level")?;
+            match keyword {
+                "start" => {
+                    if start.is_some() {
+                        return Err(WorldParseError::DuplicateStart { line: line_no, col });
+                    }
+                    start = Some(fields.chunk("starting level name")?.1.to_string());
+                    start_line = line_no;
+                }
+                "level" => {
+                    levels.push(fields.chunk("level asset")?.1.to_string());
+                    level_lines.push(line_no);
+                }
+                _ => {
+                    return Err(WorldParseError::UnknownKeyword {
+                        line: line_no,
+                        col,
+                        keyword: keyword.to_string(),
+                    })
+                }
+            }
+            fields.finish()?;
+        }
+        let start = start.ok_or(WorldParseError::MissingStart {
+            line: line_no + 1,
+            col: 1,
+        })?;
+        Ok(Self {
+            start,
+            start_line,
+            levels,
+            level_lines,
+        })
+    }
+    /// Name of the level the player starts in
+    pub fn start(&self) -> &str {
+        &self.start
+    }
+    /// Which line of the manifest the start came from
+    pub fn start_line(&self) -> usize {
+        self.start_line
+    }
+    /// Asset names of every level file in the game
+    pub fn levels(&self) -> &[String] {
+        &self.levels
+    }
+    /// Which line of the manifest each entry of [`World::levels`] came from
+    pub fn level_lines(&self) -> &[usize] {
+        &self.level_lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_world() {
+        let world = World::parse(include_str!("../content/world.txt")).unwrap();
+        assert_eq!(world.start(), "level1");
+        assert!(world.levels().iter().any(|l| l == "level2"));
+
+        let world = World::parse("# a comment\n\nlevel a\nstart b\nlevel b\n").unwrap();
+        assert_eq!(world.start(), "b");
+        assert_eq!(world.start_line(), 4);
+        assert_eq!(world.levels(), &["a".to_string(), "b".to_string()]);
+        assert_eq!(world.level_lines(), &[3, 5]);
+
+        assert_eq!(
+            World::parse("level a\n").map(|_| ()).unwrap_err(),
+            WorldParseError::MissingStart { line: 2, col: 1 }
+        );
+        assert_eq!(
+            World::parse("start a\nstart b\n").map(|_| ()).unwrap_err(),
+            WorldParseError::DuplicateStart { line: 2, col: 1 }
+        );
+        assert_eq!(
+            World::parse("start a\nroom b\n").map(|_| ()).unwrap_err(),
+            WorldParseError::UnknownKeyword {
+                line: 2,
+                col: 1,
+                keyword: "room".to_string()
+            }
+        );
+        assert_eq!(
+            World::parse("start a\nlevel b c\n")
+                .map(|_| ())
+                .unwrap_err(),
+            WorldParseError::Field(FieldError::TrailingContent { line: 2, col: 9 })
+        );
+    }
+}